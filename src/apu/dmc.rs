@@ -0,0 +1,222 @@
+use crate::region::Region;
+
+/// The timer periods in CPU cycles that the lower 4 bits of $4010 select.
+const NTSC_DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_DMC_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+const SAMPLE_START_ADDRESS: u16 = 0xC000;
+
+/// The delta modulation channel plays 1-bit delta encoded samples from CPU memory,
+/// where every bit moves the 7-bit output level up or down by 2.
+pub(super) struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    /// The byte the memory reader fetched, waiting for the output unit to take it.
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    silenced: bool,
+    pub(super) irq: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            irq_enabled: false,
+            looping: false,
+            timer_period: NTSC_DMC_RATES[0],
+            timer: 0,
+            output_level: 0,
+            sample_address: SAMPLE_START_ADDRESS,
+            sample_length: 1,
+            current_address: SAMPLE_START_ADDRESS,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silenced: true,
+            irq: false,
+        }
+    }
+}
+
+impl Dmc {
+    /// $4010: IL-- RRRR (IRQ enabled, loop, rate index)
+    pub(super) fn write_control(&mut self, byte: u8, region: Region) {
+        let rates = match region {
+            Region::Pal => &PAL_DMC_RATES,
+            Region::Ntsc | Region::Dendy => &NTSC_DMC_RATES,
+        };
+
+        self.irq_enabled = byte & 0b1000_0000 != 0;
+        self.looping = byte & 0b0100_0000 != 0;
+        self.timer_period = rates[(byte & 0b1111) as usize];
+
+        if !self.irq_enabled {
+            self.irq = false;
+        }
+    }
+
+    /// $4011: -DDD DDDD (direct load of the output level)
+    pub(super) fn write_direct_load(&mut self, byte: u8) {
+        self.output_level = byte & 0b0111_1111;
+    }
+
+    /// $4012: the sample starts at $C000 + A * 64.
+    pub(super) fn write_sample_address(&mut self, byte: u8) {
+        self.sample_address = SAMPLE_START_ADDRESS + byte as u16 * 64;
+    }
+
+    /// $4013: the sample is L * 16 + 1 bytes long.
+    pub(super) fn write_sample_length(&mut self, byte: u8) {
+        self.sample_length = byte as u16 * 16 + 1;
+    }
+
+    /// Handles bit 4 of $4015. Enabling the DMC restarts the sample if it
+    /// already finished, and disabling it drops the rest of the sample.
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        match enabled {
+            true if self.bytes_remaining == 0 => self.restart_sample(),
+            true => {}
+            false => self.bytes_remaining = 0,
+        }
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    fn restart_sample(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Returns the address the memory reader wants to fetch the next sample byte
+    /// from, once the sample buffer has been emptied.
+    pub(super) fn sample_fetch_address(&self) -> Option<u16> {
+        (self.sample_buffer.is_none() && self.bytes_remaining > 0).then_some(self.current_address)
+    }
+
+    /// Fills the sample buffer with the byte fetched from [`Self::sample_fetch_address()`].
+    /// Once the last byte is fetched the sample loops, or raises an IRQ if enabled.
+    pub(super) fn load_sample_byte(&mut self, byte: u8) {
+        self.sample_buffer = Some(byte);
+        // Addresses wrap around from $FFFF to $8000.
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart_sample();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clocked on every CPU cycle.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period - 1;
+        self.clock_output_unit();
+    }
+
+    fn clock_output_unit(&mut self) {
+        if !self.silenced {
+            match self.shift_register & 1 != 0 {
+                true if self.output_level <= 125 => self.output_level += 2,
+                false if self.output_level >= 2 => self.output_level -= 2,
+                _ => {}
+            }
+        }
+
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+
+            match self.sample_buffer.take() {
+                Some(byte) => {
+                    self.silenced = false;
+                    self.shift_register = byte;
+                }
+                None => self.silenced = true,
+            }
+        }
+    }
+
+    /// Returns the current output level (0-127).
+    pub(super) fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_bytes_are_fetched_until_the_sample_ends() {
+        let mut dmc = Dmc::default();
+        dmc.write_control(0b1000_0000, Region::Ntsc);
+        dmc.write_sample_address(0xFF);
+        // 65 bytes, the last of which wraps around to $8000.
+        dmc.write_sample_length(0x04);
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.sample_fetch_address(), Some(0xFFC0));
+        dmc.load_sample_byte(0xFF);
+        assert_eq!(dmc.sample_fetch_address(), None);
+
+        // The output unit takes the byte after its first 8 bits of silence,
+        // which makes room for the next one.
+        for _ in 0..8 * NTSC_DMC_RATES[0] {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.sample_fetch_address(), Some(0xFFC1));
+
+        for _ in 0..63 {
+            dmc.load_sample_byte(0xFF);
+            dmc.sample_buffer = None;
+        }
+        assert_eq!(dmc.sample_fetch_address(), Some(0x8000));
+        assert!(!dmc.irq);
+
+        dmc.load_sample_byte(0xFF);
+
+        assert!(!dmc.active());
+        assert!(dmc.irq);
+        assert_eq!(dmc.current_address, 0x8001);
+    }
+
+    #[test]
+    fn output_level_moves_by_2_and_clamps() {
+        let mut dmc = Dmc::default();
+        dmc.write_direct_load(126);
+        dmc.silenced = false;
+        dmc.shift_register = 0b0000_0101;
+
+        dmc.clock_output_unit();
+        assert_eq!(dmc.output(), 126);
+        dmc.clock_output_unit();
+        assert_eq!(dmc.output(), 124);
+        dmc.clock_output_unit();
+        assert_eq!(dmc.output(), 126);
+    }
+}
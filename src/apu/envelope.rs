@@ -0,0 +1,58 @@
+/// The volume envelope shared by the pulse and noise channels. It either outputs
+/// a constant volume, or a decay level that counts down from 15 once every
+/// `period + 1` quarter frames, optionally looping back to 15.
+#[derive(Default)]
+pub(super) struct Envelope {
+    start: bool,
+    looping: bool,
+    constant_volume: bool,
+    /// The constant volume, which doubles as the divider period.
+    volume: u8,
+    divider: u8,
+    decay_level: u8,
+}
+
+impl Envelope {
+    /// Handles the lower 6 bits of the channel's first register: --LC VVVV.
+    pub(super) fn write_control(&mut self, byte: u8) {
+        self.looping = byte & 0b0010_0000 != 0;
+        self.constant_volume = byte & 0b0001_0000 != 0;
+        self.volume = byte & 0b0000_1111;
+    }
+
+    /// Restarts the envelope on the next quarter frame. This happens whenever
+    /// the channel's length counter is loaded.
+    pub(super) fn restart(&mut self) {
+        self.start = true;
+    }
+
+    /// Clocked by the frame counter on every quarter frame.
+    pub(super) fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay_level = 15;
+            self.divider = self.volume;
+            return;
+        }
+
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+
+        self.divider = self.volume;
+
+        if self.decay_level > 0 {
+            self.decay_level -= 1;
+        } else if self.looping {
+            self.decay_level = 15;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        match self.constant_volume {
+            true => self.volume,
+            false => self.decay_level,
+        }
+    }
+}
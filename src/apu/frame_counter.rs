@@ -0,0 +1,147 @@
+use crate::region::Region;
+
+/// The CPU cycles at which the first four steps of the sequence happen.
+const NTSC_STEP_CYCLES: [u32; 4] = [7457, 14913, 22371, 29829];
+const PAL_STEP_CYCLES: [u32; 4] = [8313, 16627, 24939, 33253];
+/// The last step of the 5-step sequence, which replaces the 4th step.
+const NTSC_FIFTH_STEP_CYCLE: u32 = 37281;
+const PAL_FIFTH_STEP_CYCLE: u32 = 41565;
+
+/// The signal a step sends to the channels. A half frame also clocks everything
+/// that a quarter frame clocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum FrameClock {
+    /// Clocks the envelopes and the triangle's linear counter.
+    QuarterFrame,
+    /// Also clocks the length counters and the sweep units.
+    HalfFrame,
+}
+
+/// Divides the CPU clock into the quarter and half frame signals that drive the
+/// channels' envelopes, sweeps and counters, at roughly 240 Hz. In the 4-step
+/// mode it also raises the frame IRQ at the end of each sequence.
+#[derive(Default)]
+pub(super) struct FrameCounter {
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    pub(super) irq: bool,
+    cycle: u32,
+    /// Writes to $4017 restart the sequence after 3 or 4 CPU cycles.
+    reset_delay: Option<u8>,
+}
+
+impl FrameCounter {
+    /// $4017: MI-- ---- (5-step mode, IRQ inhibit). Returns a half frame signal
+    /// when switching to the 5-step mode, which clocks the channels right away.
+    pub(super) fn write(&mut self, byte: u8, odd_cycle: bool) -> Option<FrameClock> {
+        self.five_step_mode = byte & 0b1000_0000 != 0;
+        self.irq_inhibit = byte & 0b0100_0000 != 0;
+
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+
+        self.reset_delay = Some(match odd_cycle {
+            true => 4,
+            false => 3,
+        });
+
+        self.five_step_mode.then_some(FrameClock::HalfFrame)
+    }
+
+    /// Clocked on every CPU cycle.
+    pub(super) fn clock(&mut self, region: Region) -> Option<FrameClock> {
+        if let Some(delay) = self.reset_delay {
+            self.reset_delay = delay.checked_sub(1);
+
+            if delay == 1 {
+                self.cycle = 0;
+                return None;
+            }
+        }
+
+        self.cycle += 1;
+
+        let (steps, fifth_step) = match region {
+            Region::Pal => (PAL_STEP_CYCLES, PAL_FIFTH_STEP_CYCLE),
+            Region::Ntsc | Region::Dendy => (NTSC_STEP_CYCLES, NTSC_FIFTH_STEP_CYCLE),
+        };
+
+        let last_step = match self.five_step_mode {
+            true => fifth_step,
+            false => steps[3],
+        };
+
+        // The frame IRQ flag is set on the last step of the 4-step sequence, as
+        // well as the cycle before and after it.
+        if !self.five_step_mode && !self.irq_inhibit && self.cycle.abs_diff(last_step) <= 1 {
+            self.irq = true;
+        }
+
+        if self.cycle == last_step + 1 {
+            self.cycle = 0;
+        }
+
+        match self.cycle {
+            cycle if cycle == steps[0] || cycle == steps[2] => Some(FrameClock::QuarterFrame),
+            cycle if cycle == steps[1] || cycle == last_step => Some(FrameClock::HalfFrame),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_sequence(frame_counter: &mut FrameCounter, cycles: u32) -> Vec<(u32, FrameClock)> {
+        (1..=cycles)
+            .filter_map(|cycle| {
+                frame_counter
+                    .clock(Region::Ntsc)
+                    .map(|frame_clock| (cycle, frame_clock))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn four_step_sequence_raises_irq() {
+        let mut frame_counter = FrameCounter::default();
+
+        assert_eq!(
+            run_sequence(&mut frame_counter, 29828),
+            [
+                (7457, FrameClock::QuarterFrame),
+                (14913, FrameClock::HalfFrame),
+                (22371, FrameClock::QuarterFrame),
+            ]
+        );
+        assert!(frame_counter.irq);
+
+        // The sequence repeats every 29830 cycles.
+        assert_eq!(
+            run_sequence(&mut frame_counter, 2 + 7457),
+            [(1, FrameClock::HalfFrame), (7459, FrameClock::QuarterFrame)]
+        );
+    }
+
+    #[test]
+    fn five_step_sequence_and_inhibit_never_raise_irq() {
+        let mut frame_counter = FrameCounter::default();
+        assert_eq!(
+            frame_counter.write(0b1000_0000, false),
+            Some(FrameClock::HalfFrame)
+        );
+
+        let frame_clocks = run_sequence(&mut frame_counter, 3 + 37282);
+        assert_eq!(frame_clocks.len(), 4);
+        assert_eq!(frame_clocks[3], (3 + 37281, FrameClock::HalfFrame));
+        assert!(!frame_counter.irq);
+
+        frame_counter.irq = true;
+        assert_eq!(frame_counter.write(0b0100_0000, false), None);
+        assert!(!frame_counter.irq);
+        run_sequence(&mut frame_counter, 30000);
+        assert!(!frame_counter.irq);
+    }
+}
@@ -0,0 +1,48 @@
+/// The lengths that a 5-bit index written to a channel's length counter load selects.
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Silences a channel once it counts down to 0. It is clocked on every half
+/// frame unless halted, and is held at 0 while the channel is disabled in $4015.
+#[derive(Default)]
+pub(super) struct LengthCounter {
+    enabled: bool,
+    halted: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub(super) fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    /// Loads the counter from the upper 5 bits of `byte`. This is ignored while
+    /// the channel is disabled.
+    pub(super) fn load(&mut self, byte: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(byte >> 3) as usize];
+        }
+    }
+
+    /// Clocked by the frame counter on every half frame.
+    pub(super) fn clock(&mut self) {
+        if !self.halted && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    /// Whether the channel is still playing, as reported by reading $4015.
+    pub(super) fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
@@ -0,0 +1,35 @@
+/// Combines the channel outputs like the resistor networks on the NES do. The
+/// pulses share one non-linear output and the triangle, noise and DMC share
+/// another, so louder channels mute the others a bit. Returns a level from 0 to
+/// about 1.
+pub(super) fn mix(pulse_one: u8, pulse_two: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+    let pulses = (pulse_one + pulse_two) as f32;
+    let pulse_output = match pulses == 0.0 {
+        true => 0.0,
+        false => 95.88 / (8128.0 / pulses + 100.0),
+    };
+
+    let tnd = triangle as f32 / 8227.0 + noise as f32 / 12241.0 + dmc as f32 / 22638.0;
+    let tnd_output = match tnd == 0.0 {
+        true => 0.0,
+        false => 159.79 / (1.0 / tnd + 100.0),
+    };
+
+    pulse_output + tnd_output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixer_is_non_linear() {
+        assert_eq!(mix(0, 0, 0, 0, 0), 0.0);
+
+        let full = mix(15, 15, 15, 15, 127);
+        assert!(full > 0.99 && full < 1.01);
+
+        // Two pulses at 15 are less than twice as loud as one.
+        assert!(mix(15, 15, 0, 0, 0) < 2.0 * mix(15, 0, 0, 0, 0));
+    }
+}
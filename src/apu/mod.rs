@@ -0,0 +1,292 @@
+use crate::region::Region;
+use dmc::Dmc;
+use frame_counter::{FrameClock, FrameCounter};
+use noise::Noise;
+use pulse::{Pulse, PulseChannel};
+use triangle::Triangle;
+
+mod dmc;
+mod envelope;
+mod frame_counter;
+mod length_counter;
+mod mixer;
+mod noise;
+mod pulse;
+mod triangle;
+
+const PULSE_1_CONTROL: u16 = 0x4000;
+const PULSE_1_SWEEP: u16 = 0x4001;
+const PULSE_1_TIMER_LOW: u16 = 0x4002;
+const PULSE_1_TIMER_HIGH: u16 = 0x4003;
+const PULSE_2_CONTROL: u16 = 0x4004;
+const PULSE_2_SWEEP: u16 = 0x4005;
+const PULSE_2_TIMER_LOW: u16 = 0x4006;
+const PULSE_2_TIMER_HIGH: u16 = 0x4007;
+const TRIANGLE_LINEAR_COUNTER: u16 = 0x4008;
+const TRIANGLE_TIMER_LOW: u16 = 0x400A;
+const TRIANGLE_TIMER_HIGH: u16 = 0x400B;
+const NOISE_CONTROL: u16 = 0x400C;
+const NOISE_PERIOD: u16 = 0x400E;
+const NOISE_LENGTH: u16 = 0x400F;
+const DMC_CONTROL: u16 = 0x4010;
+const DMC_DIRECT_LOAD: u16 = 0x4011;
+const DMC_SAMPLE_ADDRESS: u16 = 0x4012;
+const DMC_SAMPLE_LENGTH: u16 = 0x4013;
+pub const APU_STATUS: u16 = 0x4015;
+const FRAME_COUNTER: u16 = 0x4017;
+
+const STATUS_PULSE_1: u8 = 0b0000_0001;
+const STATUS_PULSE_2: u8 = 0b0000_0010;
+const STATUS_TRIANGLE: u8 = 0b0000_0100;
+const STATUS_NOISE: u8 = 0b0000_1000;
+const STATUS_DMC: u8 = 0b0001_0000;
+const STATUS_FRAME_INTERRUPT: u8 = 0b0100_0000;
+const STATUS_DMC_INTERRUPT: u8 = 0b1000_0000;
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct Apu {
+    initialized: bool,
+    /// Decides the frame counter timing and the noise and DMC rate tables.
+    region: Region,
+    /// CPU cycles since power on. The APU runs at half the CPU clock.
+    cycles: u64,
+    pulse_one: Pulse,
+    pulse_two: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+}
+
+impl Apu {
+    /// Creates the APU but does not initialize it. Please run [`Initialize`] to
+    /// initialize the APU.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            initialized: false,
+            region: Region::default(),
+            cycles: 0,
+            pulse_one: Pulse::new(PulseChannel::One),
+            pulse_two: Pulse::new(PulseChannel::Two),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_counter: FrameCounter::default(),
+        }
+    }
+
+    /// Initialize the APU.
+    pub fn initialize(&mut self) {
+        self.initialized = true;
+    }
+
+    /// Returns the state of initialization.
+    pub fn initialized(&self) -> bool {
+        self.initialized
+    }
+
+    /// Silences all channels and clears both IRQs, like the reset button does.
+    pub fn reset(&mut self) {
+        self.write_status(0);
+        self.frame_counter.irq = false;
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// Clocks the APU once per CPU cycle.
+    pub fn clock(&mut self) {
+        if self.cycles % 2 == 1 {
+            self.pulse_one.clock_timer();
+            self.pulse_two.clock_timer();
+        }
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        if let Some(frame_clock) = self.frame_counter.clock(self.region) {
+            self.clock_frame(frame_clock);
+        }
+
+        self.cycles += 1;
+    }
+
+    fn clock_frame(&mut self, frame_clock: FrameClock) {
+        self.pulse_one.clock_quarter_frame();
+        self.pulse_two.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+
+        if frame_clock == FrameClock::HalfFrame {
+            self.pulse_one.clock_half_frame();
+            self.pulse_two.clock_half_frame();
+            self.triangle.clock_half_frame();
+            self.noise.clock_half_frame();
+        }
+    }
+
+    /// Returns the address of the next DMC sample byte, if the DMC is waiting for
+    /// one. The byte is read through the CPU bus, which stalls the CPU, and is
+    /// handed back with [`Apu::load_dmc_sample_byte()`].
+    pub fn dmc_sample_fetch_address(&self) -> Option<u16> {
+        self.dmc.sample_fetch_address()
+    }
+
+    pub fn load_dmc_sample_byte(&mut self, byte: u8) {
+        self.dmc.load_sample_byte(byte);
+    }
+
+    /// Returns the mixed output of all channels, from 0 to about 1.
+    pub fn output(&self) -> f32 {
+        mixer::mix(
+            self.pulse_one.output(),
+            self.pulse_two.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        )
+    }
+
+    /// Returns the level of the APU's IRQ line, which is held while either the
+    /// frame IRQ or the DMC IRQ is pending.
+    pub fn irq(&self) -> bool {
+        self.frame_counter.irq || self.dmc.irq
+    }
+
+    /// Handles writes to the APU registers at $4000-$4013, $4015 and $4017.
+    pub fn write_register(&mut self, address: u16, byte: u8) {
+        match address {
+            PULSE_1_CONTROL => self.pulse_one.write_control(byte),
+            PULSE_1_SWEEP => self.pulse_one.write_sweep(byte),
+            PULSE_1_TIMER_LOW => self.pulse_one.write_timer_low(byte),
+            PULSE_1_TIMER_HIGH => self.pulse_one.write_timer_high(byte),
+            PULSE_2_CONTROL => self.pulse_two.write_control(byte),
+            PULSE_2_SWEEP => self.pulse_two.write_sweep(byte),
+            PULSE_2_TIMER_LOW => self.pulse_two.write_timer_low(byte),
+            PULSE_2_TIMER_HIGH => self.pulse_two.write_timer_high(byte),
+            TRIANGLE_LINEAR_COUNTER => self.triangle.write_linear_counter(byte),
+            TRIANGLE_TIMER_LOW => self.triangle.write_timer_low(byte),
+            TRIANGLE_TIMER_HIGH => self.triangle.write_timer_high(byte),
+            NOISE_CONTROL => self.noise.write_control(byte),
+            NOISE_PERIOD => self.noise.write_period(byte, self.region),
+            NOISE_LENGTH => self.noise.write_length(byte),
+            DMC_CONTROL => self.dmc.write_control(byte, self.region),
+            DMC_DIRECT_LOAD => self.dmc.write_direct_load(byte),
+            DMC_SAMPLE_ADDRESS => self.dmc.write_sample_address(byte),
+            DMC_SAMPLE_LENGTH => self.dmc.write_sample_length(byte),
+            APU_STATUS => self.write_status(byte),
+            FRAME_COUNTER => {
+                if let Some(frame_clock) = self.frame_counter.write(byte, self.cycles % 2 == 1) {
+                    self.clock_frame(frame_clock);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Enables or disables each channel. Disabled channels have their length
+    /// counters cleared, and can't be reloaded until they are enabled again.
+    /// Writing here also acknowledges the DMC IRQ.
+    fn write_status(&mut self, byte: u8) {
+        self.pulse_one
+            .length_counter
+            .set_enabled(byte & STATUS_PULSE_1 != 0);
+        self.pulse_two
+            .length_counter
+            .set_enabled(byte & STATUS_PULSE_2 != 0);
+        self.triangle
+            .length_counter
+            .set_enabled(byte & STATUS_TRIANGLE != 0);
+        self.noise
+            .length_counter
+            .set_enabled(byte & STATUS_NOISE != 0);
+        self.dmc.set_enabled(byte & STATUS_DMC != 0);
+        self.dmc.irq = false;
+    }
+
+    /// Reading $4015 reports which channels still have a nonzero length counter,
+    /// whether the DMC has bytes left to play, and both IRQ flags. This also
+    /// acknowledges the frame IRQ.
+    pub fn read_status(&mut self) -> u8 {
+        let flags = [
+            (self.pulse_one.length_counter.active(), STATUS_PULSE_1),
+            (self.pulse_two.length_counter.active(), STATUS_PULSE_2),
+            (self.triangle.length_counter.active(), STATUS_TRIANGLE),
+            (self.noise.length_counter.active(), STATUS_NOISE),
+            (self.dmc.active(), STATUS_DMC),
+            (self.frame_counter.irq, STATUS_FRAME_INTERRUPT),
+            (self.dmc.irq, STATUS_DMC_INTERRUPT),
+        ];
+
+        self.frame_counter.irq = false;
+
+        flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .fold(0, |status, (_, flag)| status | flag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_reports_and_clears_length_counters() {
+        let mut apu = Apu::new();
+
+        // Length counters can't be loaded while their channel is disabled.
+        apu.write_register(PULSE_1_TIMER_HIGH, 0b0000_1000);
+        assert_eq!(apu.read_status(), 0);
+
+        apu.write_register(APU_STATUS, STATUS_PULSE_1 | STATUS_PULSE_2);
+        apu.write_register(PULSE_1_TIMER_HIGH, 0b0000_1000);
+        apu.write_register(PULSE_2_TIMER_HIGH, 0b0000_1000);
+        assert_eq!(apu.read_status(), STATUS_PULSE_1 | STATUS_PULSE_2);
+
+        apu.write_register(APU_STATUS, STATUS_PULSE_2);
+        assert_eq!(apu.read_status(), STATUS_PULSE_2);
+
+        // Length index 1 loads a length of 254, which takes 254 half frames to run out.
+        for _ in 0..253 {
+            apu.clock_frame(FrameClock::HalfFrame);
+        }
+        assert_eq!(apu.read_status(), STATUS_PULSE_2);
+        apu.clock_frame(FrameClock::HalfFrame);
+        assert_eq!(apu.read_status(), 0);
+    }
+
+    #[test]
+    fn frame_irq_is_acknowledged_by_reading_status() {
+        let mut apu = Apu::new();
+
+        for _ in 0..29830 {
+            apu.clock();
+        }
+
+        assert!(apu.irq());
+        assert_eq!(apu.read_status(), STATUS_FRAME_INTERRUPT);
+        assert!(!apu.irq());
+        assert_eq!(apu.read_status(), 0);
+    }
+
+    #[test]
+    fn dmc_irq_is_reported_until_status_is_written() {
+        let mut apu = Apu::new();
+        apu.write_register(DMC_CONTROL, 0b1000_0000);
+        apu.write_register(DMC_SAMPLE_LENGTH, 0);
+        apu.write_register(APU_STATUS, STATUS_DMC);
+        assert_eq!(apu.read_status(), STATUS_DMC);
+
+        assert_eq!(apu.dmc_sample_fetch_address(), Some(0xC000));
+        apu.load_dmc_sample_byte(0x00);
+        assert!(apu.irq());
+        assert_eq!(apu.read_status(), STATUS_DMC_INTERRUPT);
+
+        apu.write_register(APU_STATUS, 0);
+        assert!(!apu.irq());
+    }
+}
@@ -0,0 +1,126 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+use crate::region::Region;
+
+/// The timer periods in CPU cycles that the lower 4 bits of $400E select.
+const NTSC_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+/// Outputs pseudo-random noise from a 15-bit linear feedback shift register.
+pub(super) struct Noise {
+    /// Feeds back from bit 6 instead of bit 1, which gives a short, metallic sequence.
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift_register: u16,
+    envelope: Envelope,
+    pub(super) length_counter: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            short_mode: false,
+            timer_period: NTSC_NOISE_PERIODS[0],
+            timer: 0,
+            // The shift register is loaded with 1 on power up.
+            shift_register: 1,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+}
+
+impl Noise {
+    /// $400C: --LC VVVV (length counter halt/envelope loop, constant volume,
+    /// volume/envelope period)
+    pub(super) fn write_control(&mut self, byte: u8) {
+        self.length_counter.set_halted(byte & 0b0010_0000 != 0);
+        self.envelope.write_control(byte);
+    }
+
+    /// $400E: M--- PPPP (mode, period index)
+    pub(super) fn write_period(&mut self, byte: u8, region: Region) {
+        let periods = match region {
+            Region::Pal => &PAL_NOISE_PERIODS,
+            Region::Ntsc | Region::Dendy => &NTSC_NOISE_PERIODS,
+        };
+
+        self.short_mode = byte & 0b1000_0000 != 0;
+        self.timer_period = periods[(byte & 0b1111) as usize];
+    }
+
+    /// $400F: LLLL L--- (length counter load). This also restarts the envelope.
+    pub(super) fn write_length(&mut self, byte: u8) {
+        self.length_counter.load(byte);
+        self.envelope.restart();
+    }
+
+    /// Clocked on every CPU cycle.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period - 1;
+
+        let tap = match self.short_mode {
+            true => 6,
+            false => 1,
+        };
+        let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    /// Returns the current volume (0-15).
+    pub(super) fn output(&self) -> u8 {
+        if self.shift_register & 1 != 0 || !self.length_counter.active() {
+            return 0;
+        }
+
+        self.envelope.output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence_length(short_mode: bool) -> usize {
+        let mut noise = Noise::default();
+        noise.write_period(u8::from(short_mode) << 7, Region::Ntsc);
+
+        let start = noise.shift_register;
+        let mut steps = 0;
+
+        loop {
+            for _ in 0..noise.timer_period {
+                noise.clock_timer();
+            }
+            steps += 1;
+
+            if noise.shift_register == start {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    fn lfsr_modes_repeat_after_32767_and_93_steps() {
+        assert_eq!(sequence_length(false), 32767);
+        assert_eq!(sequence_length(true), 93);
+    }
+}
@@ -0,0 +1,223 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+
+/// The 8-step waveforms of the four duty cycles: 12.5%, 25%, 50% and 25% negated.
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// Pulse channels are muted when their period would go past 11 bits.
+const MAX_TIMER_PERIOD: u16 = 0x07FF;
+/// Pulse channels are muted when their period is below 8 (frequencies above 12.4 kHz).
+const MIN_TIMER_PERIOD: u16 = 8;
+
+/// The two pulse channels only differ in how their sweep units negate.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum PulseChannel {
+    /// Pulse 1 ($4000-$4003) negates with ones' complement, subtracting one more.
+    One,
+    /// Pulse 2 ($4004-$4007) negates with two's complement.
+    Two,
+}
+
+/// Periodically adds or subtracts a shifted copy of the timer period to itself.
+#[derive(Default)]
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+pub(super) struct Pulse {
+    channel: PulseChannel,
+    duty: u8,
+    sequence_step: usize,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    sweep: Sweep,
+    pub(super) length_counter: LengthCounter,
+}
+
+impl Pulse {
+    pub(super) fn new(channel: PulseChannel) -> Self {
+        Self {
+            channel,
+            duty: 0,
+            sequence_step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            sweep: Sweep::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+
+    /// $4000/$4004: DDLC VVVV (duty, length counter halt/envelope loop,
+    /// constant volume, volume/envelope period)
+    pub(super) fn write_control(&mut self, byte: u8) {
+        self.duty = byte >> 6;
+        self.length_counter.set_halted(byte & 0b0010_0000 != 0);
+        self.envelope.write_control(byte);
+    }
+
+    /// $4001/$4005: EPPP NSSS (enabled, period, negate, shift)
+    pub(super) fn write_sweep(&mut self, byte: u8) {
+        self.sweep.enabled = byte & 0b1000_0000 != 0;
+        self.sweep.period = (byte >> 4) & 0b111;
+        self.sweep.negate = byte & 0b0000_1000 != 0;
+        self.sweep.shift = byte & 0b111;
+        self.sweep.reload = true;
+    }
+
+    /// $4002/$4006: the lower 8 bits of the timer period.
+    pub(super) fn write_timer_low(&mut self, byte: u8) {
+        self.timer_period = (self.timer_period & 0x0700) | byte as u16;
+    }
+
+    /// $4003/$4007: LLLL LTTT (length counter load, upper 3 bits of the timer
+    /// period). This also restarts the envelope and the duty sequence.
+    pub(super) fn write_timer_high(&mut self, byte: u8) {
+        self.timer_period = (self.timer_period & 0x00FF) | ((byte as u16 & 0b111) << 8);
+        self.length_counter.load(byte);
+        self.sequence_step = 0;
+        self.envelope.restart();
+    }
+
+    /// Clocked on every APU cycle (every other CPU cycle).
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period;
+        self.sequence_step = (self.sequence_step + 1) % 8;
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+        self.clock_sweep();
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.muted() {
+            self.timer_period = self.sweep_target_period();
+        }
+
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    /// The period the sweep unit would move to. This is calculated continuously,
+    /// so it can mute the channel even while the sweep unit is disabled.
+    fn sweep_target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+
+        match (self.sweep.negate, self.channel) {
+            (false, _) => self.timer_period + change,
+            (true, PulseChannel::One) => self.timer_period.saturating_sub(change + 1),
+            (true, PulseChannel::Two) => self.timer_period.saturating_sub(change),
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.timer_period < MIN_TIMER_PERIOD || self.sweep_target_period() > MAX_TIMER_PERIOD
+    }
+
+    /// Returns the current volume (0-15).
+    pub(super) fn output(&self) -> u8 {
+        if self.muted()
+            || !self.length_counter.active()
+            || DUTY_SEQUENCES[self.duty as usize][self.sequence_step] == 0
+        {
+            return 0;
+        }
+
+        self.envelope.output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweeping_down(channel: PulseChannel) -> Pulse {
+        let mut pulse = Pulse::new(channel);
+        pulse.length_counter.set_enabled(true);
+        pulse.write_timer_low(0x00);
+        pulse.write_timer_high(0x01);
+        // Enabled, period 0, negate, shift 1.
+        pulse.write_sweep(0b1000_1001);
+        pulse
+    }
+
+    #[test]
+    fn pulse_1_sweeps_down_with_ones_complement() {
+        let mut pulse_one = sweeping_down(PulseChannel::One);
+        let mut pulse_two = sweeping_down(PulseChannel::Two);
+
+        pulse_one.clock_half_frame();
+        pulse_two.clock_half_frame();
+
+        assert_eq!(pulse_one.timer_period, 0x100 - 0x80 - 1);
+        assert_eq!(pulse_two.timer_period, 0x100 - 0x80);
+    }
+
+    #[test]
+    fn sweep_target_past_11_bits_mutes_even_when_disabled() {
+        let mut pulse = Pulse::new(PulseChannel::Two);
+        pulse.length_counter.set_enabled(true);
+        pulse.write_control(0b0101_1111);
+        pulse.write_timer_low(0x00);
+        pulse.write_timer_high(0x06);
+        pulse.sequence_step = 1;
+
+        // Disabled, but with a shift of 1 the target period is $600 + $300.
+        pulse.write_sweep(0b0000_0001);
+        assert_eq!(pulse.output(), 0);
+
+        // Negated, the target period is $600 - $300.
+        pulse.write_sweep(0b0000_1001);
+        assert_eq!(pulse.output(), 15);
+
+        pulse.clock_half_frame();
+        assert_eq!(pulse.timer_period, 0x600);
+    }
+
+    #[test]
+    fn envelope_decays_and_length_counter_silences() {
+        let mut pulse = Pulse::new(PulseChannel::One);
+        pulse.length_counter.set_enabled(true);
+        // 50% duty, envelope period 0.
+        pulse.write_control(0b1000_0000);
+        pulse.write_timer_low(0x10);
+        // Length index 3 loads a length of 2.
+        pulse.write_timer_high(0b0001_1000);
+        pulse.sequence_step = 1;
+
+        pulse.clock_quarter_frame();
+        assert_eq!(pulse.output(), 15);
+        pulse.clock_quarter_frame();
+        assert_eq!(pulse.output(), 14);
+
+        pulse.clock_half_frame();
+        assert_eq!(pulse.output(), 14);
+        pulse.clock_half_frame();
+        assert_eq!(pulse.output(), 0);
+    }
+}
@@ -0,0 +1,107 @@
+use super::length_counter::LengthCounter;
+
+/// The triangle steps down from 15 to 0, then back up to 15.
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+/// The triangle channel has no volume control. Instead of silencing its output,
+/// the length and linear counters stop the sequencer wherever it is.
+#[derive(Default)]
+pub(super) struct Triangle {
+    /// Halts the length counter and keeps reloading the linear counter.
+    control: bool,
+    linear_counter_period: u8,
+    linear_counter: u8,
+    linear_counter_reload: bool,
+    sequence_step: usize,
+    timer_period: u16,
+    timer: u16,
+    pub(super) length_counter: LengthCounter,
+}
+
+impl Triangle {
+    /// $4008: CRRR RRRR (length counter halt/linear counter control, linear counter load)
+    pub(super) fn write_linear_counter(&mut self, byte: u8) {
+        self.control = byte & 0b1000_0000 != 0;
+        self.length_counter.set_halted(self.control);
+        self.linear_counter_period = byte & 0b0111_1111;
+    }
+
+    /// $400A: the lower 8 bits of the timer period.
+    pub(super) fn write_timer_low(&mut self, byte: u8) {
+        self.timer_period = (self.timer_period & 0x0700) | byte as u16;
+    }
+
+    /// $400B: LLLL LTTT (length counter load, upper 3 bits of the timer period).
+    /// This also reloads the linear counter on the next quarter frame.
+    pub(super) fn write_timer_high(&mut self, byte: u8) {
+        self.timer_period = (self.timer_period & 0x00FF) | ((byte as u16 & 0b111) << 8);
+        self.length_counter.load(byte);
+        self.linear_counter_reload = true;
+    }
+
+    /// Clocked on every CPU cycle, which makes the triangle an octave lower than
+    /// a pulse with the same period.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period;
+
+        if self.linear_counter > 0 && self.length_counter.active() {
+            self.sequence_step = (self.sequence_step + 1) % TRIANGLE_SEQUENCE.len();
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        if self.linear_counter_reload {
+            self.linear_counter = self.linear_counter_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_counter_reload = false;
+        }
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    /// Returns the current level (0-15).
+    pub(super) fn output(&self) -> u8 {
+        TRIANGLE_SEQUENCE[self.sequence_step]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequencer_only_runs_while_both_counters_are_nonzero() {
+        let mut triangle = Triangle::default();
+        triangle.length_counter.set_enabled(true);
+        triangle.write_linear_counter(0b0000_0010);
+        triangle.write_timer_low(0);
+        triangle.write_timer_high(0b0000_1000);
+
+        // The linear counter isn't loaded until the next quarter frame.
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 15);
+
+        triangle.clock_quarter_frame();
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 14);
+
+        triangle.clock_quarter_frame();
+        triangle.clock_quarter_frame();
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 14);
+    }
+}
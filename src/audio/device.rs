@@ -0,0 +1,91 @@
+use super::AudioSink;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// The most audio that is queued for the device. With video pacing, the frame
+/// timer and the sound card's clock drift apart, so past this the oldest
+/// samples are dropped instead of letting the latency grow.
+const MAX_QUEUED_SECS: f64 = 0.2;
+
+/// Plays the audio on the default output device. Samples are queued here and
+/// pulled from the queue by the device's audio thread.
+pub struct DeviceSink {
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    sample_rate: u32,
+    // The stream stops playing once it is dropped.
+    _stream: Stream,
+}
+
+impl DeviceSink {
+    pub fn open() -> Result<Self, Box<dyn std::error::Error>> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device is available")?;
+        let supported_config = device.default_output_config()?;
+        let sample_rate = supported_config.sample_rate().0;
+        let config = supported_config.config();
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+
+        let stream = match supported_config.sample_format() {
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, queue.clone())?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, queue.clone())?,
+            _ => build_stream::<f32>(&device, &config, queue.clone())?,
+        };
+        stream.play()?;
+
+        Ok(Self {
+            queue,
+            sample_rate,
+            _stream: stream,
+        })
+    }
+}
+
+/// Builds an output stream that plays the mono samples in `queue` on every
+/// channel. When the queue runs dry, the last sample is held to avoid clicks.
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+) -> Result<Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let mut last_sample = 0.0;
+
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let mut queue = queue.lock().unwrap();
+
+            for frame in data.chunks_mut(channels) {
+                last_sample = queue.pop_front().unwrap_or(last_sample);
+                frame.fill(T::from_sample(last_sample));
+            }
+        },
+        |err| eprintln!("Audio output error: {err}"),
+        None,
+    )
+}
+
+impl AudioSink for DeviceSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) {
+        let max_queued = (MAX_QUEUED_SECS * self.sample_rate as f64) as usize;
+        let mut queue = self.queue.lock().unwrap();
+
+        queue.extend(samples);
+        let excess = queue.len().saturating_sub(max_queued);
+        queue.drain(..excess);
+    }
+
+    fn buffered_secs(&self) -> Option<f64> {
+        Some(self.queue.lock().unwrap().len() as f64 / self.sample_rate as f64)
+    }
+}
@@ -0,0 +1,83 @@
+use clap::ValueEnum;
+use std::path::PathBuf;
+
+pub use resampler::Resampler;
+pub use wav::WavSink;
+
+#[cfg(feature = "audio-device")]
+mod device;
+mod resampler;
+mod wav;
+
+/// The sample rate used by sinks that aren't tied to a device.
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+/// Receives the resampled, mono audio output of the emulator.
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+
+    fn write(&mut self, samples: &[f32]);
+
+    /// Returns how many seconds of audio are queued but not played yet, for sinks
+    /// that play in real time. The emulator can pace itself off this instead of
+    /// the render loop.
+    fn buffered_secs(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// Throws the audio away, for headless runs or when no device is available.
+pub struct NullSink {
+    sample_rate: u32,
+}
+
+impl NullSink {
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, _samples: &[f32]) {}
+}
+
+/// Where the audio goes, selected from the command line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum AudioOutput {
+    /// Play through the default output device.
+    #[cfg_attr(feature = "audio-device", default)]
+    Device,
+    /// Record to a `.wav` file.
+    Wav,
+    /// Don't output any audio.
+    #[cfg_attr(not(feature = "audio-device"), default)]
+    None,
+}
+
+#[derive(Clone, Debug)]
+pub struct AudioOptions {
+    pub output: AudioOutput,
+    pub wav_path: PathBuf,
+    /// The sample rate of the `.wav` file. Devices use their own sample rate.
+    pub sample_rate: u32,
+}
+
+impl AudioOptions {
+    /// Opens the sink. Device sinks have to be opened on the thread that uses them.
+    pub fn open_sink(&self) -> Result<Box<dyn AudioSink>, Box<dyn std::error::Error>> {
+        Ok(match self.output {
+            #[cfg(feature = "audio-device")]
+            AudioOutput::Device => Box::new(device::DeviceSink::open()?),
+            #[cfg(not(feature = "audio-device"))]
+            AudioOutput::Device => {
+                return Err("this build was compiled without the audio-device feature".into())
+            }
+            AudioOutput::Wav => Box::new(WavSink::create(&self.wav_path, self.sample_rate)?),
+            AudioOutput::None => Box::new(NullSink::new(self.sample_rate)),
+        })
+    }
+}
@@ -0,0 +1,158 @@
+use std::f64::consts::PI;
+
+/// The amount of sub-sample positions a level change can be placed at.
+const PHASES: usize = 64;
+/// The width of the band-limited step, in output samples.
+const TAPS: usize = 16;
+/// The cutoff frequency as a fraction of the output sample rate, a bit below Nyquist.
+const CUTOFF: f64 = 0.45;
+/// The NES itself high-passes its audio output at around 90 Hz, which also
+/// removes the DC offset of the mixer.
+const HIGH_PASS_HZ: f64 = 90.0;
+
+/// Resamples the mixer output from the CPU clock rate down to the sink's sample rate.
+///
+/// The mixer output only changes in steps, so instead of filtering every input
+/// sample, each step is drawn into the output as a band-limited step (a windowed
+/// sinc impulse, integrated), positioned with sub-sample precision. This keeps
+/// everything above the output's Nyquist frequency from aliasing back down.
+pub struct Resampler {
+    /// Output samples per input sample.
+    step: f64,
+    /// The position of the next input sample, in output samples from the start of `deltas`.
+    position: f64,
+    /// The changes in level between consecutive output samples.
+    deltas: Vec<f32>,
+    /// The impulse response for each phase, which always sums to 1.
+    kernel: Vec<[f32; TAPS]>,
+    level: f32,
+    accumulator: f32,
+    high_pass_factor: f32,
+    high_pass_input: f32,
+    high_pass_output: f32,
+}
+
+impl Resampler {
+    pub fn new(input_rate: f64, output_rate: u32) -> Self {
+        let output_rate = output_rate as f64;
+
+        Self {
+            step: output_rate / input_rate,
+            position: 0.0,
+            deltas: Vec::new(),
+            kernel: (0..PHASES).map(kernel_phase).collect(),
+            level: 0.0,
+            accumulator: 0.0,
+            high_pass_factor: (output_rate / (output_rate + 2.0 * PI * HIGH_PASS_HZ)) as f32,
+            high_pass_input: 0.0,
+            high_pass_output: 0.0,
+        }
+    }
+
+    /// Adds the next input sample.
+    pub fn push(&mut self, level: f32) {
+        let delta = level - self.level;
+
+        if delta != 0.0 {
+            self.level = level;
+
+            let start = self.position as usize;
+            let phase = ((self.position - start as f64) * PHASES as f64) as usize;
+            let end = start + TAPS;
+
+            if self.deltas.len() < end {
+                self.deltas.resize(end, 0.0);
+            }
+
+            for (output, weight) in self.deltas[start..end].iter_mut().zip(&self.kernel[phase]) {
+                *output += delta * weight;
+            }
+        }
+
+        self.position += self.step;
+    }
+
+    /// Appends the output samples that no future input can change anymore.
+    pub fn drain_into(&mut self, output: &mut Vec<f32>) {
+        let ready = self.position as usize;
+
+        if self.deltas.len() < ready {
+            self.deltas.resize(ready, 0.0);
+        }
+
+        for delta in self.deltas.drain(..ready) {
+            self.accumulator += delta;
+
+            self.high_pass_output = self.high_pass_factor
+                * (self.high_pass_output + self.accumulator - self.high_pass_input);
+            self.high_pass_input = self.accumulator;
+
+            output.push(self.high_pass_output);
+        }
+
+        self.position -= ready as f64;
+    }
+}
+
+/// Samples a Blackman windowed sinc for a step that happens `phase / PHASES` of
+/// an output sample late. The impulse is delayed by half its width to make it causal.
+fn kernel_phase(phase: usize) -> [f32; TAPS] {
+    let offset = phase as f64 / PHASES as f64;
+    let half_width = TAPS as f64 / 2.0;
+
+    let mut weights = [0.0; TAPS];
+    for (tap, weight) in weights.iter_mut().enumerate() {
+        let x = tap as f64 - half_width - offset;
+
+        let sinc = match x == 0.0 {
+            true => 1.0,
+            false => (2.0 * PI * CUTOFF * x).sin() / (2.0 * PI * CUTOFF * x),
+        };
+        let window_position = (x + half_width) / TAPS as f64;
+        let window = 0.42 - 0.5 * (2.0 * PI * window_position).cos()
+            + 0.08 * (4.0 * PI * window_position).cos();
+
+        *weight = sinc * window;
+    }
+
+    let sum: f64 = weights.iter().sum();
+    weights.map(|weight| (weight / sum) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CPU_HZ: f64 = 1_789_773.0;
+
+    #[test]
+    fn produces_samples_at_the_output_rate() {
+        let mut resampler = Resampler::new(CPU_HZ, 48_000);
+        let mut output = Vec::new();
+
+        for _ in 0..CPU_HZ as usize {
+            resampler.push(0.0);
+        }
+        resampler.drain_into(&mut output);
+
+        assert!(output.len().abs_diff(48_000) <= 1);
+    }
+
+    #[test]
+    fn steps_are_smoothed_without_losing_their_height() {
+        let mut resampler = Resampler::new(CPU_HZ, 48_000);
+        let mut output = Vec::new();
+
+        for _ in 0..CPU_HZ as usize / 1000 {
+            resampler.push(0.5);
+        }
+        resampler.drain_into(&mut output);
+
+        // The step is spread around the middle of the kernel instead of jumping at once...
+        assert!(output[TAPS / 2 - 3].abs() < 0.05);
+        assert!(output[TAPS / 2] > 0.1 && output[TAPS / 2] < 0.5);
+        // ...but still reaches its full height before the high-pass pulls it back.
+        let peak = output.iter().cloned().fold(0.0, f32::max);
+        assert!(peak > 0.45 && peak < 0.55);
+    }
+}
@@ -0,0 +1,116 @@
+use super::AudioSink;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const HEADER_BYTES: u32 = 44;
+const CHANNELS: u16 = 1;
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// Records the audio to a 16-bit mono PCM `.wav` file. The chunk sizes in the
+/// header are filled in when the sink is dropped.
+pub struct WavSink<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    data_bytes: u32,
+}
+
+impl WavSink<BufWriter<File>> {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(HEADER_BYTES - 8).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            sample_rate,
+            data_bytes: 0,
+        })
+    }
+
+    /// Writes the final chunk sizes into the header.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(HEADER_BYTES - 8 + self.data_bytes).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(HEADER_BYTES as u64 - 4))?;
+        self.writer.write_all(&self.data_bytes.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+
+    fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+
+        self.data_bytes += (samples.len() * 2) as u32;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) {
+        if let Err(err) = self.write_samples(samples) {
+            eprintln!("Failed to write audio samples: {err}");
+        }
+    }
+}
+
+impl<W: Write + Seek> Drop for WavSink<W> {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("Failed to finish the audio recording: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_sizes_are_filled_in_on_finish() {
+        let mut bytes = Vec::new();
+
+        {
+            let mut sink = WavSink::new(Cursor::new(&mut bytes), 44_100).unwrap();
+            sink.write(&[0.0, 1.0, -1.0]);
+        }
+
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 6);
+        assert_eq!(
+            u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+            44_100
+        );
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 6);
+        assert_eq!(i16::from_le_bytes([bytes[46], bytes[47]]), i16::MAX);
+    }
+}
@@ -0,0 +1,108 @@
+use super::{open_bus, ClockableMapper, Connections, Mirroring, KB};
+use crate::cpu::CpuContainer;
+use crate::ines::Ines;
+use crate::ppu::Ppu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const PROGRAM_BANK_SIZE: usize = KB * 32;
+
+const PROGRAM_BANK: u8 = 0b0000_0111;
+/// Picks which 1 KB of CIRAM all four nametables show.
+const NAMETABLE_UPPER: u8 = 0b0001_0000;
+
+/// AxROM (mapper 7). Writing to $8000-$FFFF switches the 32 KB PRG bank and the
+/// single-screen nametable. The board has 8 KB of CHR-RAM.
+///
+/// Only the rare AMROM boards have bus conflicts, the ANROM and AOROM boards
+/// most games shipped on don't, and some games rely on that, so writes are taken
+/// as is.
+pub(super) struct Axrom {
+    program_rom: Vec<u8>,
+    character_ram: [u8; KB * 8],
+    program_bank: u8,
+    mirroring: Mirroring,
+    connections: Connections,
+}
+
+impl Axrom {
+    pub fn new(ines: Ines) -> Self {
+        Self {
+            program_rom: ines.program_rom,
+            character_ram: [0; KB * 8],
+            program_bank: 0,
+            mirroring: Mirroring::SingleScreenLower,
+            connections: Connections::default(),
+        }
+    }
+}
+
+impl ClockableMapper for Axrom {
+    type Cpu = Rc<RefCell<CpuContainer>>;
+    type Ppu = Rc<RefCell<Ppu>>;
+
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => {
+                let banks = (self.program_rom.len() / PROGRAM_BANK_SIZE).max(1);
+                let bank = self.program_bank as usize % banks;
+                self.program_rom[(bank * PROGRAM_BANK_SIZE + (address as usize - 0x8000))
+                    % self.program_rom.len()]
+            }
+            _ => open_bus(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, byte: u8) {
+        if address < 0x8000 {
+            return;
+        }
+
+        self.program_bank = byte & PROGRAM_BANK;
+        self.mirroring = match byte & NAMETABLE_UPPER != 0 {
+            true => Mirroring::SingleScreenUpper,
+            false => Mirroring::SingleScreenLower,
+        };
+
+        self.connections.set_mirroring(self.mirroring);
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.character_ram[address as usize & 0x1FFF]
+    }
+
+    fn ppu_write(&mut self, address: u16, byte: u8) {
+        self.character_ram[address as usize & 0x1FFF] = byte;
+    }
+
+    fn clock(&mut self) {}
+
+    fn initialize(&mut self, cpu: Self::Cpu, ppu: Self::Ppu) {
+        self.connections.connect(cpu, ppu, self.mirroring);
+    }
+
+    fn initialized(&self) -> bool {
+        self.connections.connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::numbered_rom;
+    use super::*;
+
+    #[test]
+    fn switches_the_program_bank_and_nametable() {
+        let mut axrom = Axrom::new(numbered_rom(7, PROGRAM_BANK_SIZE, 8, 0, 0));
+        assert_eq!(axrom.read(0xFFFF), 0);
+
+        // No bus conflict, even though the ROM holds 0 here.
+        axrom.write(0x8000, NAMETABLE_UPPER | 5);
+        assert_eq!((axrom.read(0x8000), axrom.read(0xFFFF)), (5, 5));
+        assert_eq!(axrom.mirroring, Mirroring::SingleScreenUpper);
+
+        axrom.write(0x8000, 2);
+        assert_eq!(axrom.read(0x8000), 2);
+        assert_eq!(axrom.mirroring, Mirroring::SingleScreenLower);
+    }
+}
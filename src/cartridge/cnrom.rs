@@ -0,0 +1,99 @@
+use super::{open_bus, ClockableMapper, Connections, Mirroring, KB};
+use crate::cpu::CpuContainer;
+use crate::ines::Ines;
+use crate::ppu::Ppu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const CHARACTER_BANK_SIZE: usize = KB * 8;
+
+/// CNROM (mapper 3). Writing to $8000-$FFFF switches the 8 KB CHR-ROM bank,
+/// while the 16 or 32 KB of PRG-ROM is fixed like on NROM.
+/// Writes have bus conflicts.
+pub(super) struct Cnrom {
+    program_rom: Vec<u8>,
+    character_rom: Vec<u8>,
+    character_bank: u8,
+    mirroring: Mirroring,
+    connections: Connections,
+}
+
+impl Cnrom {
+    pub fn new(ines: Ines) -> Self {
+        let mut character_rom = ines.character_rom;
+        // A header without CHR-ROM makes little sense here, but don't index an empty Vec.
+        if character_rom.is_empty() {
+            character_rom.resize(CHARACTER_BANK_SIZE, 0);
+        }
+
+        Self {
+            mirroring: Mirroring::from_header(&ines.header),
+            program_rom: ines.program_rom,
+            character_rom,
+            character_bank: 0,
+            connections: Connections::default(),
+        }
+    }
+}
+
+impl ClockableMapper for Cnrom {
+    type Cpu = Rc<RefCell<CpuContainer>>;
+    type Ppu = Rc<RefCell<Ppu>>;
+
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            // 16 KB of PRG-ROM is mirrored at $C000.
+            0x8000..=0xFFFF => {
+                self.program_rom[(address as usize - 0x8000) % self.program_rom.len()]
+            }
+            _ => open_bus(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, byte: u8) {
+        if address >= 0x8000 {
+            self.character_bank = self.bus_conflict(address, byte);
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let banks = self.character_rom.len() / CHARACTER_BANK_SIZE;
+        let bank = self.character_bank as usize % banks;
+
+        self.character_rom[bank * CHARACTER_BANK_SIZE + (address as usize & 0x1FFF)]
+    }
+
+    fn ppu_write(&mut self, _address: u16, _byte: u8) {}
+
+    fn clock(&mut self) {}
+
+    fn initialize(&mut self, cpu: Self::Cpu, ppu: Self::Ppu) {
+        self.connections.connect(cpu, ppu, self.mirroring);
+    }
+
+    fn initialized(&self) -> bool {
+        self.connections.connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::numbered_rom;
+    use super::*;
+
+    #[test]
+    fn switches_the_character_bank_with_bus_conflicts() {
+        let mut ines = numbered_rom(3, KB * 16, 1, CHARACTER_BANK_SIZE, 4);
+        ines.program_rom.fill(0xFF);
+        ines.program_rom[0] = 0x01;
+
+        let mut cnrom = Cnrom::new(ines);
+
+        cnrom.write(0x8001, 2);
+        assert_eq!(cnrom.ppu_read(0x1000), 2);
+
+        // $C000 mirrors $8000, which holds 1, and 3 & 1 = 1.
+        cnrom.write(0xC000, 3);
+        assert_eq!(cnrom.ppu_read(0x0000), 1);
+    }
+}
@@ -0,0 +1,119 @@
+use super::{open_bus, ClockableMapper, Connections, Mirroring, KB};
+use crate::cpu::CpuContainer;
+use crate::ines::Ines;
+use crate::ppu::Ppu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const PROGRAM_BANK_SIZE: usize = KB * 32;
+const CHARACTER_BANK_SIZE: usize = KB * 8;
+
+const CHARACTER_BANK: u8 = 0b0000_0011;
+const PROGRAM_BANK: u8 = 0b0011_0000;
+
+/// GxROM (mapper 66). Writing to $8000-$FFFF switches the 32 KB PRG bank with
+/// bits 4-5 and the 8 KB CHR bank with bits 0-1.
+/// Writes have bus conflicts.
+pub(super) struct Gxrom {
+    program_rom: Vec<u8>,
+    character_memory: Vec<u8>,
+    has_character_ram: bool,
+    /// The last value written, with both banks in it.
+    bank_select: u8,
+    mirroring: Mirroring,
+    connections: Connections,
+}
+
+impl Gxrom {
+    pub fn new(ines: Ines) -> Self {
+        let has_character_ram = ines.header.character_rom_size_multiplier == 0;
+        let mut character_memory = ines.character_rom;
+        if has_character_ram {
+            character_memory.resize(CHARACTER_BANK_SIZE, 0);
+        }
+
+        Self {
+            mirroring: Mirroring::from_header(&ines.header),
+            program_rom: ines.program_rom,
+            character_memory,
+            has_character_ram,
+            bank_select: 0,
+            connections: Connections::default(),
+        }
+    }
+
+    fn character_address(&self, address: u16) -> usize {
+        let banks = self.character_memory.len() / CHARACTER_BANK_SIZE;
+        let bank = (self.bank_select & CHARACTER_BANK) as usize % banks;
+
+        bank * CHARACTER_BANK_SIZE + (address as usize & 0x1FFF)
+    }
+}
+
+impl ClockableMapper for Gxrom {
+    type Cpu = Rc<RefCell<CpuContainer>>;
+    type Ppu = Rc<RefCell<Ppu>>;
+
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => {
+                let banks = (self.program_rom.len() / PROGRAM_BANK_SIZE).max(1);
+                let bank = ((self.bank_select & PROGRAM_BANK) >> 4) as usize % banks;
+                self.program_rom[(bank * PROGRAM_BANK_SIZE + (address as usize - 0x8000))
+                    % self.program_rom.len()]
+            }
+            _ => open_bus(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, byte: u8) {
+        if address >= 0x8000 {
+            self.bank_select = self.bus_conflict(address, byte);
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.character_memory[self.character_address(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, byte: u8) {
+        if self.has_character_ram {
+            let address = self.character_address(address);
+            self.character_memory[address] = byte;
+        }
+    }
+
+    fn clock(&mut self) {}
+
+    fn initialize(&mut self, cpu: Self::Cpu, ppu: Self::Ppu) {
+        self.connections.connect(cpu, ppu, self.mirroring);
+    }
+
+    fn initialized(&self) -> bool {
+        self.connections.connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::numbered_rom;
+    use super::*;
+
+    #[test]
+    fn switches_both_banks_with_bus_conflicts() {
+        // Every PRG bank is filled with 0xFF except for a byte holding its number.
+        let mut ines = numbered_rom(66, PROGRAM_BANK_SIZE, 4, CHARACTER_BANK_SIZE, 4);
+        for bank in ines.program_rom.chunks_mut(PROGRAM_BANK_SIZE) {
+            bank[1..].fill(0xFF);
+        }
+
+        let mut gxrom = Gxrom::new(ines);
+
+        gxrom.write(0x8001, 0x23);
+        assert_eq!((gxrom.read(0x8000), gxrom.ppu_read(0x0000)), (2, 3));
+
+        // $8000 holds 2 in bank 2, so only bit 1 survives.
+        gxrom.write(0x8000, 0x13);
+        assert_eq!((gxrom.read(0x8000), gxrom.ppu_read(0x0000)), (0, 2));
+    }
+}
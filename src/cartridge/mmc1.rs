@@ -0,0 +1,353 @@
+use super::{open_bus, ClockableMapper, Connections, Mirroring, KB};
+use crate::cpu::CpuContainer;
+use crate::ines::Ines;
+use crate::ppu::Ppu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const PROGRAM_BANK_SIZE: usize = KB * 16;
+const CHARACTER_BANK_SIZE: usize = KB * 4;
+const PROGRAM_RAM_SIZE: usize = KB * 8;
+/// SUROM boards have 512 KB of PRG-ROM, which is more than the 4-bit PRG bank
+/// can reach, so bit 4 of the CHR bank picks the 256 KB half instead.
+const SUROM_PROGRAM_ROM_SIZE: usize = KB * 512;
+
+/// Written to any register, resets the shift register and the PRG bank mode.
+const SHIFT_RESET: u8 = 0b1000_0000;
+/// Set in the shift register after a reset. Once it is shifted out, the
+/// register is complete.
+const SHIFT_REGISTER_EMPTY: u8 = 0b1_0000;
+
+const CONTROL_MIRRORING: u8 = 0b0_0011;
+const CONTROL_PROGRAM_MODE: u8 = 0b0_1100;
+const CONTROL_CHARACTER_4K: u8 = 0b1_0000;
+/// The PRG bank mode at power on, where the last bank is fixed at $C000.
+const PROGRAM_MODE_FIX_LAST: u8 = 0b0_1100;
+const PROGRAM_MODE_FIX_FIRST: u8 = 0b0_1000;
+
+/// Set in the PRG bank register to disable PRG-RAM.
+const PROGRAM_RAM_DISABLE: u8 = 0b1_0000;
+/// Bit 4 of the CHR banks either disables PRG-RAM (SNROM), or picks the 256 KB
+/// PRG-ROM half (SUROM).
+const CHARACTER_BANK_BIT_4: u8 = 0b1_0000;
+
+/// MMC1 (mapper 1), also known as SxROM.
+///
+/// The registers are loaded serially: five writes to $8000-$FFFF shift in bit 0
+/// each, and the fifth write picks the register by its address. When two writes
+/// land on consecutive CPU cycles, like the dummy write of a read-modify-write
+/// instruction, the second one is ignored.
+pub(super) struct Mmc1 {
+    program_rom: Vec<u8>,
+    program_ram: [u8; PROGRAM_RAM_SIZE],
+    /// The CHR-ROM, or 8 KB of CHR-RAM when the header has no CHR-ROM.
+    character_memory: Vec<u8>,
+    has_character_ram: bool,
+    shift_register: u8,
+    control: u8,
+    character_bank_0: u8,
+    character_bank_1: u8,
+    program_bank: u8,
+    /// Cleared by every write and set again by the next clock, so writes
+    /// within the same instruction can be told apart.
+    clocked_since_write: bool,
+    /// Whether the PPU last fetched from the upper pattern table, which decides
+    /// which CHR bank drives the board-specific bit 4 in 4 KB CHR mode.
+    last_character_bank_1: bool,
+    mirroring: Mirroring,
+    connections: Connections,
+}
+
+impl Mmc1 {
+    pub fn new(ines: Ines) -> Self {
+        let has_character_ram = ines.header.character_rom_size_multiplier == 0;
+        let mut character_memory = ines.character_rom;
+        if has_character_ram {
+            character_memory.resize(KB * 8, 0);
+        }
+
+        let control = PROGRAM_MODE_FIX_LAST;
+
+        Self {
+            program_rom: ines.program_rom,
+            program_ram: [0; PROGRAM_RAM_SIZE],
+            character_memory,
+            has_character_ram,
+            shift_register: SHIFT_REGISTER_EMPTY,
+            control,
+            character_bank_0: 0,
+            character_bank_1: 0,
+            program_bank: 0,
+            clocked_since_write: true,
+            last_character_bank_1: false,
+            mirroring: control_mirroring(control),
+            connections: Connections::default(),
+        }
+    }
+
+    /// The CHR bank whose bit 4 is used for the board-specific wiring.
+    fn outer_bank_register(&self) -> u8 {
+        match self.control & CONTROL_CHARACTER_4K != 0 && self.last_character_bank_1 {
+            true => self.character_bank_1,
+            false => self.character_bank_0,
+        }
+    }
+
+    fn program_ram_enabled(&self) -> bool {
+        // SNROM boards have 8 KB of CHR, so bit 4 of the CHR bank isn't needed
+        // for CHR and disables PRG-RAM instead.
+        let snrom_disabled = self.character_memory.len() <= KB * 8
+            && self.program_rom.len() < SUROM_PROGRAM_ROM_SIZE
+            && self.outer_bank_register() & CHARACTER_BANK_BIT_4 != 0;
+
+        self.program_bank & PROGRAM_RAM_DISABLE == 0 && !snrom_disabled
+    }
+
+    /// Returns the 16 KB PRG-ROM bank mapped at `address`.
+    fn program_bank_at(&self, address: u16) -> usize {
+        let bank = (self.program_bank & 0x0F) as usize;
+        let last_bank = 0x0F;
+        let upper_half = address >= 0xC000;
+
+        let bank = match self.control & CONTROL_PROGRAM_MODE {
+            PROGRAM_MODE_FIX_LAST if upper_half => last_bank,
+            PROGRAM_MODE_FIX_FIRST if !upper_half => 0,
+            PROGRAM_MODE_FIX_LAST | PROGRAM_MODE_FIX_FIRST => bank,
+            // 32 KB mode ignores the lowest bit.
+            _ => (bank & !1) | upper_half as usize,
+        };
+
+        let outer_bank = match self.program_rom.len() >= SUROM_PROGRAM_ROM_SIZE {
+            true => (self.outer_bank_register() & CHARACTER_BANK_BIT_4) as usize,
+            false => 0,
+        };
+
+        (outer_bank | bank) % (self.program_rom.len() / PROGRAM_BANK_SIZE).max(1)
+    }
+
+    /// Returns the offset into CHR memory for a pattern table address.
+    fn character_offset(&self, address: u16) -> usize {
+        let address = address as usize & 0x1FFF;
+
+        let upper_half = address >= CHARACTER_BANK_SIZE;
+        let bank = match (self.control & CONTROL_CHARACTER_4K != 0, upper_half) {
+            (true, false) => self.character_bank_0,
+            (true, true) => self.character_bank_1,
+            // 8 KB mode ignores the lowest bit.
+            (false, _) => (self.character_bank_0 & !1) | upper_half as u8,
+        } as usize;
+
+        (bank * CHARACTER_BANK_SIZE + (address % CHARACTER_BANK_SIZE)) % self.character_memory.len()
+    }
+
+    fn write_shift_register(&mut self, address: u16, byte: u8) {
+        if byte & SHIFT_RESET != 0 {
+            self.shift_register = SHIFT_REGISTER_EMPTY;
+            self.write_control(self.control | PROGRAM_MODE_FIX_LAST);
+            return;
+        }
+
+        let complete = self.shift_register & 1 != 0;
+        self.shift_register = (self.shift_register >> 1) | ((byte & 1) << 4);
+
+        if complete {
+            let value = self.shift_register;
+            self.shift_register = SHIFT_REGISTER_EMPTY;
+
+            match address {
+                0x8000..=0x9FFF => self.write_control(value),
+                0xA000..=0xBFFF => self.character_bank_0 = value,
+                0xC000..=0xDFFF => self.character_bank_1 = value,
+                _ => self.program_bank = value,
+            }
+        }
+    }
+
+    fn write_control(&mut self, value: u8) {
+        self.control = value;
+
+        let mirroring = control_mirroring(value);
+        if mirroring != self.mirroring {
+            self.mirroring = mirroring;
+
+            self.connections.set_mirroring(mirroring);
+        }
+    }
+}
+
+fn control_mirroring(control: u8) -> Mirroring {
+    match control & CONTROL_MIRRORING {
+        0 => Mirroring::SingleScreenLower,
+        1 => Mirroring::SingleScreenUpper,
+        2 => Mirroring::Vertical,
+        _ => Mirroring::Horizontal,
+    }
+}
+
+impl ClockableMapper for Mmc1 {
+    type Cpu = Rc<RefCell<CpuContainer>>;
+    type Ppu = Rc<RefCell<Ppu>>;
+
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.program_ram_enabled() => {
+                self.program_ram[address as usize - 0x6000]
+            }
+            0x8000..=0xFFFF => {
+                let offset = address as usize % PROGRAM_BANK_SIZE;
+                self.program_rom[self.program_bank_at(address) * PROGRAM_BANK_SIZE + offset]
+            }
+            _ => open_bus(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, byte: u8) {
+        match address {
+            0x6000..=0x7FFF if self.program_ram_enabled() => {
+                self.program_ram[address as usize - 0x6000] = byte;
+            }
+            0x8000..=0xFFFF => {
+                if self.clocked_since_write {
+                    self.write_shift_register(address, byte);
+                }
+                self.clocked_since_write = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.last_character_bank_1 = address & 0x1000 != 0;
+        self.character_memory[self.character_offset(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, byte: u8) {
+        if self.has_character_ram {
+            let offset = self.character_offset(address);
+            self.character_memory[offset] = byte;
+        }
+    }
+
+    fn clock(&mut self) {
+        self.clocked_since_write = true;
+    }
+
+    fn initialize(&mut self, cpu: Self::Cpu, ppu: Self::Ppu) {
+        self.connections.connect(cpu, ppu, self.mirroring);
+    }
+
+    fn initialized(&self) -> bool {
+        self.connections.connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::numbered_rom;
+    use super::*;
+
+    /// Creates an MMC1 board where every 16 KB PRG bank and 4 KB CHR bank is
+    /// filled with its own number.
+    fn mmc1(program_banks: usize, character_banks: usize) -> Mmc1 {
+        Mmc1::new(numbered_rom(
+            1,
+            PROGRAM_BANK_SIZE,
+            program_banks,
+            CHARACTER_BANK_SIZE,
+            character_banks,
+        ))
+    }
+
+    /// Loads a register through the shift register, one bit per write.
+    fn write_register(mmc1: &mut Mmc1, address: u16, value: u8) {
+        for bit in 0..5 {
+            mmc1.write(address, value >> bit);
+            mmc1.clock();
+        }
+    }
+
+    #[test]
+    fn program_banks_follow_the_bank_mode() {
+        let mut mmc1 = mmc1(8, 2);
+
+        // The last bank is fixed at $C000 on power on.
+        write_register(&mut mmc1, 0xE000, 3);
+        assert_eq!((mmc1.read(0x8000), mmc1.read(0xC000)), (3, 7));
+
+        write_register(&mut mmc1, 0x8000, PROGRAM_MODE_FIX_FIRST);
+        assert_eq!((mmc1.read(0x8000), mmc1.read(0xC000)), (0, 3));
+
+        // 32 KB mode ignores the lowest bit of the bank.
+        write_register(&mut mmc1, 0x8000, 0);
+        assert_eq!((mmc1.read(0x8000), mmc1.read(0xFFFF)), (2, 3));
+    }
+
+    #[test]
+    fn character_banks_switch_in_4k_or_8k() {
+        let mut mmc1 = mmc1(2, 8);
+
+        write_register(&mut mmc1, 0xA000, 5);
+        write_register(&mut mmc1, 0xC000, 2);
+        assert_eq!((mmc1.ppu_read(0x0000), mmc1.ppu_read(0x1000)), (4, 5));
+
+        write_register(&mut mmc1, 0x8000, CONTROL_CHARACTER_4K);
+        assert_eq!((mmc1.ppu_read(0x0000), mmc1.ppu_read(0x1000)), (5, 2));
+    }
+
+    #[test]
+    fn writes_on_consecutive_cycles_are_ignored() {
+        let mut mmc1 = mmc1(8, 2);
+
+        // Like INC on a register, the second write of each pair is ignored.
+        for bit in [1, 0, 1, 0, 0] {
+            mmc1.write(0xE000, bit);
+            mmc1.write(0xE000, 1);
+            mmc1.clock();
+        }
+        assert_eq!(mmc1.read(0x8000), 5);
+
+        // Resets are ignored the same way.
+        mmc1.write(0xE000, 1);
+        mmc1.write(0xE000, SHIFT_RESET);
+        mmc1.clock();
+        assert_ne!(mmc1.shift_register, SHIFT_REGISTER_EMPTY);
+    }
+
+    #[test]
+    fn control_sets_the_mirroring() {
+        let mut mmc1 = mmc1(2, 2);
+
+        write_register(&mut mmc1, 0x8000, 2);
+        assert_eq!(mmc1.mirroring, Mirroring::Vertical);
+
+        write_register(&mut mmc1, 0x8000, 1);
+        assert_eq!(mmc1.mirroring, Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn program_ram_can_be_disabled() {
+        let mut mmc1 = mmc1(2, 2);
+
+        mmc1.write(0x6000, 0x42);
+        mmc1.clock();
+        assert_eq!(mmc1.read(0x6000), 0x42);
+
+        write_register(&mut mmc1, 0xE000, PROGRAM_RAM_DISABLE);
+        assert_eq!(mmc1.read(0x6000), 0x60);
+        write_register(&mut mmc1, 0xE000, 0);
+        assert_eq!(mmc1.read(0x6000), 0x42);
+
+        // SNROM disables it through bit 4 of the CHR bank as well.
+        write_register(&mut mmc1, 0xA000, CHARACTER_BANK_BIT_4);
+        assert_eq!(mmc1.read(0x6000), 0x60);
+    }
+
+    #[test]
+    fn surom_selects_the_256k_half_with_the_character_bank() {
+        let mut mmc1 = mmc1(32, 0);
+
+        assert_eq!(mmc1.read(0xC000), 15);
+        write_register(&mut mmc1, 0xA000, CHARACTER_BANK_BIT_4);
+        assert_eq!((mmc1.read(0x8000), mmc1.read(0xC000)), (16, 31));
+    }
+}
@@ -0,0 +1,225 @@
+use super::{open_bus, ClockableMapper, Connections, Mirroring, KB};
+use crate::cpu::CpuContainer;
+use crate::ines::Ines;
+use crate::ppu::Ppu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const CHARACTER_BANK_SIZE: usize = KB * 4;
+const PROGRAM_RAM_SIZE: usize = KB * 8;
+const MMC4_MAPPER: u8 = 10;
+
+/// The tiles that flip the latches when their patterns are fetched.
+const LATCH_TILE_FD: u8 = 0xFD;
+const LATCH_TILE_FE: u8 = 0xFE;
+
+/// MMC2 (mapper 9, PxROM) and MMC4 (mapper 10, FxROM).
+///
+/// Each 4 KB pattern table has two CHR banks, and a latch that picks between
+/// them. Fetching the pattern of tile $FD or $FE flips the latch of that pattern
+/// table, which takes effect from the next fetch on. Games put these tiles
+/// where they want the bank to change, like the ring ropes in Punch-Out!!.
+///
+/// The MMC2 switches an 8 KB PRG bank at $8000 and fixes the last three. The
+/// MMC4 switches a 16 KB bank and fixes the last one, and adds 8 KB of PRG-RAM.
+pub(super) struct Mmc2 {
+    program_rom: Vec<u8>,
+    program_ram: [u8; PROGRAM_RAM_SIZE],
+    character_rom: Vec<u8>,
+    mmc4: bool,
+    program_bank: u8,
+    /// The banks of each pattern table, for when its latch holds $FD and $FE.
+    character_banks: [[u8; 2]; 2],
+    /// Whether the latch of each pattern table holds $FE.
+    latches: [bool; 2],
+    mirroring: Mirroring,
+    connections: Connections,
+}
+
+impl Mmc2 {
+    pub fn new(ines: Ines) -> Self {
+        let mut character_rom = ines.character_rom;
+        if character_rom.is_empty() {
+            character_rom.resize(KB * 8, 0);
+        }
+
+        Self {
+            mirroring: Mirroring::from_header(&ines.header),
+            mmc4: ines.header.mapper_number == MMC4_MAPPER,
+            program_rom: ines.program_rom,
+            program_ram: [0; PROGRAM_RAM_SIZE],
+            character_rom,
+            program_bank: 0,
+            character_banks: [[0; 2]; 2],
+            // Both latches hold $FE at power on.
+            latches: [true; 2],
+            connections: Connections::default(),
+        }
+    }
+
+    fn program_bank_size(&self) -> usize {
+        match self.mmc4 {
+            true => KB * 16,
+            false => KB * 8,
+        }
+    }
+
+    fn program_address(&self, address: u16) -> usize {
+        let bank_size = self.program_bank_size();
+        let banks = self.program_rom.len() / bank_size;
+        let offset = address as usize - 0x8000;
+
+        let bank = match offset / bank_size {
+            0 => self.program_bank as usize % banks,
+            // The fixed banks are the last ones, in order.
+            slot => banks - (0x8000 / bank_size) + slot,
+        };
+
+        bank * bank_size + offset % bank_size
+    }
+
+    /// Flips the latch of the pattern table if the fetch was from tile $FD or
+    /// $FE. The latches react to the fetches of the upper bit plane, which are
+    /// $xFD8-$xFDF and $xFE8-$xFEF, except that the lower latch of the MMC2 only
+    /// reacts to exactly $0FD8 and $0FE8.
+    fn update_latch(&mut self, address: u16) {
+        let table = (address as usize >> 12) & 1;
+        let tile = (address >> 4) as u8;
+        let row = address & 0x0F;
+
+        if self.mmc4 || table == 1 || row == 0x08 {
+            match (tile, row >= 0x08) {
+                (LATCH_TILE_FD, true) => self.latches[table] = false,
+                (LATCH_TILE_FE, true) => self.latches[table] = true,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl ClockableMapper for Mmc2 {
+    type Cpu = Rc<RefCell<CpuContainer>>;
+    type Ppu = Rc<RefCell<Ppu>>;
+
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.mmc4 => self.program_ram[address as usize - 0x6000],
+            0x8000..=0xFFFF => self.program_rom[self.program_address(address)],
+            _ => open_bus(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, byte: u8) {
+        match address {
+            0x6000..=0x7FFF if self.mmc4 => self.program_ram[address as usize - 0x6000] = byte,
+            0xA000..=0xAFFF => self.program_bank = byte & 0x0F,
+            0xB000..=0xEFFF => {
+                let register = (address as usize - 0xB000) >> 12;
+                self.character_banks[register / 2][register % 2] = byte & 0x1F;
+            }
+            0xF000..=0xFFFF => {
+                self.mirroring = match byte & 1 {
+                    0 => Mirroring::Vertical,
+                    _ => Mirroring::Horizontal,
+                };
+
+                self.connections.set_mirroring(self.mirroring);
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let address = address & 0x1FFF;
+        let table = (address as usize >> 12) & 1;
+        let bank = self.character_banks[table][self.latches[table] as usize] as usize;
+        let banks = self.character_rom.len() / CHARACTER_BANK_SIZE;
+
+        let byte = self.character_rom
+            [(bank % banks) * CHARACTER_BANK_SIZE + address as usize % CHARACTER_BANK_SIZE];
+        self.update_latch(address);
+
+        byte
+    }
+
+    fn ppu_write(&mut self, _address: u16, _byte: u8) {}
+
+    fn clock(&mut self) {}
+
+    fn initialize(&mut self, cpu: Self::Cpu, ppu: Self::Ppu) {
+        self.connections.connect(cpu, ppu, self.mirroring);
+    }
+
+    fn initialized(&self) -> bool {
+        self.connections.connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::numbered_rom;
+    use super::*;
+
+    /// Creates an MMC2 or MMC4 board with 128 KB of PRG-ROM and 128 KB of
+    /// CHR-ROM, where every 8 KB PRG bank and 4 KB CHR bank is filled with its
+    /// own number.
+    fn board(mapper_number: u8) -> Mmc2 {
+        Mmc2::new(numbered_rom(
+            mapper_number,
+            KB * 8,
+            16,
+            CHARACTER_BANK_SIZE,
+            32,
+        ))
+    }
+
+    fn write_character_banks(mmc2: &mut Mmc2) {
+        for (register, bank) in [(0xB000, 1), (0xC000, 2), (0xD000, 3), (0xE000, 4)] {
+            mmc2.write(register, bank);
+        }
+    }
+
+    #[test]
+    fn program_banks_are_8_kb_on_the_mmc2_and_16_kb_on_the_mmc4() {
+        let mut mmc2 = board(9);
+        mmc2.write(0xA000, 3);
+        let banks = [0x8000, 0xA000, 0xC000, 0xE000].map(|address| mmc2.read(address));
+        assert_eq!(banks, [3, 13, 14, 15]);
+
+        let mut mmc4 = board(MMC4_MAPPER);
+        mmc4.write(0xA000, 3);
+        let banks = [0x8000, 0xA000, 0xC000, 0xE000].map(|address| mmc4.read(address));
+        assert_eq!(banks, [6, 7, 14, 15]);
+    }
+
+    #[test]
+    fn fetching_the_latch_tiles_flips_the_banks_after_the_fetch() {
+        let mut mmc2 = board(9);
+        write_character_banks(&mut mmc2);
+        assert_eq!((mmc2.ppu_read(0x0000), mmc2.ppu_read(0x1000)), (2, 4));
+
+        // The fetch that flips the latch still sees the old bank.
+        assert_eq!(mmc2.ppu_read(0x1FD8), 4);
+        assert_eq!((mmc2.ppu_read(0x0000), mmc2.ppu_read(0x1000)), (2, 3));
+
+        // Any row of the upper latch tiles counts.
+        mmc2.ppu_read(0x1FEF);
+        assert_eq!(mmc2.ppu_read(0x1000), 4);
+
+        mmc2.ppu_read(0x0FD8);
+        assert_eq!(mmc2.ppu_read(0x0000), 1);
+    }
+
+    #[test]
+    fn only_the_mmc4_flips_the_lower_latch_on_any_row() {
+        let mut mmc2 = board(9);
+        write_character_banks(&mut mmc2);
+        mmc2.ppu_read(0x0FDA);
+        assert_eq!(mmc2.ppu_read(0x0000), 2);
+
+        let mut mmc4 = board(MMC4_MAPPER);
+        write_character_banks(&mut mmc4);
+        mmc4.ppu_read(0x0FDA);
+        assert_eq!(mmc4.ppu_read(0x0000), 1);
+    }
+}
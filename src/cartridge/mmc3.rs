@@ -0,0 +1,367 @@
+use super::{open_bus, ClockableMapper, Connections, Mirroring, KB};
+use crate::cpu::CpuContainer;
+use crate::ines::Ines;
+use crate::ppu::Ppu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const PROGRAM_BANK_SIZE: usize = KB * 8;
+const CHARACTER_BANK_SIZE: usize = KB;
+const PROGRAM_RAM_SIZE: usize = KB * 8;
+
+const BANK_SELECT_REGISTER: u8 = 0b0000_0111;
+/// Swaps the switchable $8000 bank with the fixed second to last bank at $C000.
+const BANK_SELECT_PROGRAM_MODE: u8 = 0b0100_0000;
+/// Swaps the two 2 KB banks at $0000 with the four 1 KB banks at $1000.
+const BANK_SELECT_CHARACTER_INVERSION: u8 = 0b1000_0000;
+
+const PROGRAM_RAM_ENABLE: u8 = 0b1000_0000;
+const PROGRAM_RAM_WRITE_PROTECT: u8 = 0b0100_0000;
+
+const A12: u16 = 0x1000;
+/// A12 has to stay low for about three CPU cycles before a rise clocks the IRQ
+/// counter. This keeps the short drops between the sprite fetches from counting.
+const A12_LOW_DOTS: u8 = 10;
+
+/// The NES 2.0 submapper of boards with the MMC3A.
+const MMC3A_SUBMAPPER: u8 = 4;
+
+/// MMC3 (mapper 4), also known as TxROM.
+///
+/// Two 8 KB PRG banks and six CHR banks (two of 2 KB and four of 1 KB) are
+/// switched by writing the register index to $8000 and the bank to $8001.
+///
+/// The IRQ counter is clocked by rises of A12 on the PPU bus. With the usual
+/// setup of the background at $0000 and the sprites at $1000, that happens once
+/// per scanline when the sprite patterns are fetched.
+pub(super) struct Mmc3 {
+    program_rom: Vec<u8>,
+    program_ram: [u8; PROGRAM_RAM_SIZE],
+    /// The CHR-ROM, or 8 KB of CHR-RAM when the header has no CHR-ROM.
+    character_memory: Vec<u8>,
+    has_character_ram: bool,
+    bank_select: u8,
+    /// R0-R5 are the CHR banks, R6 and R7 the PRG banks.
+    banks: [u8; 8],
+    program_ram_protect: u8,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    /// The MMC3A only raises an IRQ when the counter reaches 0 by counting down
+    /// or by a reload requested through $C001. The MMC3B raises one whenever the
+    /// counter is 0 after a clock, so a latch of 0 raises one every scanline.
+    mmc3a: bool,
+    /// How many dots A12 has been low for, up to [`A12_LOW_DOTS`].
+    a12_low_dots: u8,
+    mirroring: Mirroring,
+    connections: Connections,
+}
+
+impl Mmc3 {
+    pub fn new(ines: Ines) -> Self {
+        let has_character_ram = ines.header.character_rom_size_multiplier == 0;
+        let mut character_memory = ines.character_rom;
+        if has_character_ram {
+            character_memory.resize(KB * 8, 0);
+        }
+
+        Self {
+            mirroring: Mirroring::from_header(&ines.header),
+            mmc3a: ines.header.submapper_number == MMC3A_SUBMAPPER,
+            program_rom: ines.program_rom,
+            program_ram: [0; PROGRAM_RAM_SIZE],
+            character_memory,
+            has_character_ram,
+            bank_select: 0,
+            banks: [0; 8],
+            program_ram_protect: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_low_dots: 0,
+            connections: Connections::default(),
+        }
+    }
+
+    fn program_address(&self, address: u16) -> usize {
+        let banks = self.program_rom.len() / PROGRAM_BANK_SIZE;
+        let swapped = self.bank_select & BANK_SELECT_PROGRAM_MODE != 0;
+
+        let bank = match ((address as usize - 0x8000) / PROGRAM_BANK_SIZE, swapped) {
+            (0, false) | (2, true) => self.banks[6] as usize,
+            (0, true) | (2, false) => banks.saturating_sub(2),
+            (1, _) => self.banks[7] as usize,
+            _ => banks - 1,
+        };
+
+        (bank % banks) * PROGRAM_BANK_SIZE + address as usize % PROGRAM_BANK_SIZE
+    }
+
+    fn character_address(&self, address: u16) -> usize {
+        let mut address = address as usize & 0x1FFF;
+        if self.bank_select & BANK_SELECT_CHARACTER_INVERSION != 0 {
+            address ^= 0x1000;
+        }
+
+        // The 2 KB banks ignore the lowest bit of their bank number.
+        let bank = match address / CHARACTER_BANK_SIZE {
+            0 => self.banks[0] & 0xFE,
+            1 => self.banks[0] | 1,
+            2 => self.banks[1] & 0xFE,
+            3 => self.banks[1] | 1,
+            slot => self.banks[slot - 2],
+        };
+        let banks = self.character_memory.len() / CHARACTER_BANK_SIZE;
+
+        (bank as usize % banks) * CHARACTER_BANK_SIZE + address % CHARACTER_BANK_SIZE
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let previous_counter = self.irq_counter;
+
+        match self.irq_counter == 0 || self.irq_reload {
+            true => self.irq_counter = self.irq_latch,
+            false => self.irq_counter -= 1,
+        }
+
+        let raise_irq = match self.mmc3a {
+            true => self.irq_counter == 0 && (previous_counter != 0 || self.irq_reload),
+            false => self.irq_counter == 0,
+        };
+        self.irq_reload = false;
+
+        if raise_irq && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl ClockableMapper for Mmc3 {
+    type Cpu = Rc<RefCell<CpuContainer>>;
+    type Ppu = Rc<RefCell<Ppu>>;
+
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.program_ram_protect & PROGRAM_RAM_ENABLE != 0 => {
+                self.program_ram[address as usize - 0x6000]
+            }
+            0x8000..=0xFFFF => self.program_rom[self.program_address(address)],
+            _ => open_bus(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, byte: u8) {
+        let even = address & 1 == 0;
+
+        match address {
+            0x6000..=0x7FFF
+                if self.program_ram_protect & (PROGRAM_RAM_ENABLE | PROGRAM_RAM_WRITE_PROTECT)
+                    == PROGRAM_RAM_ENABLE =>
+            {
+                self.program_ram[address as usize - 0x6000] = byte;
+            }
+            0x8000..=0x9FFF if even => self.bank_select = byte,
+            0x8000..=0x9FFF => {
+                self.banks[(self.bank_select & BANK_SELECT_REGISTER) as usize] = byte;
+            }
+            // Four-screen boards have their mirroring hardwired.
+            0xA000..=0xBFFF if even && self.mirroring != Mirroring::FourScreen => {
+                self.mirroring = match byte & 1 {
+                    0 => Mirroring::Vertical,
+                    _ => Mirroring::Horizontal,
+                };
+
+                self.connections.set_mirroring(self.mirroring);
+            }
+            0xA000..=0xBFFF if !even => self.program_ram_protect = byte,
+            0xC000..=0xDFFF if even => self.irq_latch = byte,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.character_memory[self.character_address(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, byte: u8) {
+        if self.has_character_ram {
+            let address = self.character_address(address);
+            self.character_memory[address] = byte;
+        }
+    }
+
+    fn ppu_bus(&mut self, address: u16) {
+        if address & A12 == 0 {
+            self.a12_low_dots = (self.a12_low_dots + 1).min(A12_LOW_DOTS);
+            return;
+        }
+
+        if self.a12_low_dots == A12_LOW_DOTS {
+            self.clock_irq_counter();
+        }
+        self.a12_low_dots = 0;
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn clock(&mut self) {}
+
+    fn initialize(&mut self, cpu: Self::Cpu, ppu: Self::Ppu) {
+        self.connections.connect(cpu, ppu, self.mirroring);
+    }
+
+    fn initialized(&self) -> bool {
+        self.connections.connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::numbered_rom;
+    use super::*;
+
+    /// Creates an MMC3 board with 16 PRG banks of 8 KB and 64 CHR banks of 1 KB,
+    /// each filled with its own number.
+    fn mmc3(submapper_number: u8) -> Mmc3 {
+        let mut ines = numbered_rom(4, PROGRAM_BANK_SIZE, 16, CHARACTER_BANK_SIZE, 64);
+        ines.header.submapper_number = submapper_number;
+
+        Mmc3::new(ines)
+    }
+
+    fn write_bank(mmc3: &mut Mmc3, bank_select: u8, bank: u8) {
+        mmc3.write(0x8000, bank_select);
+        mmc3.write(0x8001, bank);
+    }
+
+    /// Drives the PPU bus like a scanline with the background at $0000 and the
+    /// sprites at $1000 does.
+    fn scanline(mmc3: &mut Mmc3) {
+        for _ in 0..256 {
+            mmc3.ppu_bus(0x0000);
+        }
+        for _ in 0..64 {
+            mmc3.ppu_bus(0x1FF0);
+        }
+        for _ in 0..21 {
+            mmc3.ppu_bus(0x0000);
+        }
+    }
+
+    #[test]
+    fn program_banks_follow_the_program_mode() {
+        let mut mmc3 = mmc3(0);
+        write_bank(&mut mmc3, 6, 3);
+        write_bank(&mut mmc3, 7, 5);
+
+        let read_banks = |mmc3: &Mmc3| [0x8000, 0xA000, 0xC000, 0xE000].map(|a| mmc3.read(a));
+        assert_eq!(read_banks(&mmc3), [3, 5, 14, 15]);
+
+        mmc3.write(0x8000, BANK_SELECT_PROGRAM_MODE);
+        assert_eq!(read_banks(&mmc3), [14, 5, 3, 15]);
+    }
+
+    #[test]
+    fn character_banks_follow_the_inversion() {
+        let mut mmc3 = mmc3(0);
+        // The lowest bit of the 2 KB banks is ignored.
+        write_bank(&mut mmc3, 0, 9);
+        write_bank(&mut mmc3, 1, 20);
+        for register in 2..6 {
+            write_bank(&mut mmc3, register, 30 + register);
+        }
+
+        let read_banks = |mmc3: &mut Mmc3| {
+            (0..8)
+                .map(|slot| mmc3.ppu_read(slot * 0x400))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(read_banks(&mut mmc3), [8, 9, 20, 21, 32, 33, 34, 35]);
+
+        mmc3.write(0x8000, BANK_SELECT_CHARACTER_INVERSION);
+        assert_eq!(read_banks(&mut mmc3), [32, 33, 34, 35, 8, 9, 20, 21]);
+    }
+
+    #[test]
+    fn program_ram_can_be_disabled_and_write_protected() {
+        let mut mmc3 = mmc3(0);
+        mmc3.write(0x6000, 0x12);
+        assert_eq!(mmc3.read(0x6000), 0x60);
+
+        mmc3.write(0xA001, PROGRAM_RAM_ENABLE);
+        mmc3.write(0x6000, 0x12);
+        assert_eq!(mmc3.read(0x6000), 0x12);
+
+        mmc3.write(0xA001, PROGRAM_RAM_ENABLE | PROGRAM_RAM_WRITE_PROTECT);
+        mmc3.write(0x6000, 0x34);
+        assert_eq!(mmc3.read(0x6000), 0x12);
+    }
+
+    #[test]
+    fn irq_is_raised_after_the_latched_number_of_scanlines() {
+        let mut mmc3 = mmc3(0);
+        mmc3.write(0xC000, 2);
+        mmc3.write(0xC001, 0);
+        mmc3.write(0xE001, 0);
+
+        // The first scanline reloads the counter, the next two count it down.
+        scanline(&mut mmc3);
+        scanline(&mut mmc3);
+        assert!(!mmc3.irq());
+        scanline(&mut mmc3);
+        assert!(mmc3.irq());
+
+        mmc3.write(0xE000, 0);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn short_drops_of_a12_are_filtered_out() {
+        let mut mmc3 = mmc3(0);
+        mmc3.write(0xC000, 0);
+        mmc3.write(0xE001, 0);
+
+        mmc3.ppu_bus(0x1000);
+        for _ in 0..A12_LOW_DOTS - 1 {
+            mmc3.ppu_bus(0x0000);
+        }
+        mmc3.ppu_bus(0x1000);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn a_latch_of_0_raises_an_irq_every_scanline_on_the_mmc3b_only() {
+        for (submapper_number, expected) in [
+            (0, [true, true, true]),
+            (MMC3A_SUBMAPPER, [true, false, false]),
+        ] {
+            let mut mmc3 = mmc3(submapper_number);
+            mmc3.write(0xC000, 0);
+            mmc3.write(0xC001, 0);
+            mmc3.write(0xE001, 0);
+
+            for expected in expected {
+                scanline(&mut mmc3);
+                assert_eq!(mmc3.irq(), expected);
+                // Acknowledge the IRQ and enable it again.
+                mmc3.write(0xE000, 0);
+                mmc3.write(0xE001, 0);
+            }
+        }
+    }
+}
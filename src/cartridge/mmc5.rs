@@ -0,0 +1,864 @@
+use super::{open_bus, ClockableMapper, Connections, Mirroring, KB};
+use crate::cpu::CpuContainer;
+use crate::ines::Ines;
+use crate::ppu::Ppu;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+const PROGRAM_BANK_SIZE: usize = KB * 8;
+/// The most PRG-RAM any MMC5 board has, which covers all of them.
+const PROGRAM_RAM_SIZE: usize = KB * 64;
+const CHARACTER_BANK_SIZE: usize = KB;
+const EXTENDED_RAM_SIZE: usize = KB;
+
+/// Set in the PRG bank registers of $8000-$DFFF to map ROM instead of RAM.
+const PROGRAM_BANK_ROM: u8 = 0b1000_0000;
+/// The values $5102 and $5103 need to hold for PRG-RAM to be writable.
+const PROGRAM_RAM_WRITABLE: [u8; 2] = [0b10, 0b01];
+
+const PPUCTRL: u16 = 0x2000;
+const PPUCTRL_SPRITE_SIZE: u8 = 0b0010_0000;
+
+const SPLIT_ENABLE: u8 = 0b1000_0000;
+/// Puts the split on the right of the threshold instead of the left.
+const SPLIT_RIGHT: u8 = 0b0100_0000;
+const SPLIT_THRESHOLD: u8 = 0b0001_1111;
+
+const IRQ_ENABLE: u8 = 0b1000_0000;
+const STATUS_IRQ_PENDING: u8 = 0b1000_0000;
+const STATUS_IN_FRAME: u8 = 0b0100_0000;
+
+/// The PPU only stops reading outside of rendering. After about three CPU
+/// cycles without a read, the MMC5 takes it that the frame is over.
+const IDLE_DOTS: u8 = 9;
+const ATTRIBUTE_TABLE_OFFSET: usize = 0x3C0;
+const VISIBLE_SCANLINES: u16 = 240;
+
+/// Where each nametable comes from, picked by two bits of $5105 each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NametableSource {
+    Ciram(u8),
+    ExtendedRam,
+    Fill,
+}
+
+/// What the 1 KB of ExRAM is used for, picked by $5104.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ExtendedRamMode {
+    Nametable,
+    /// Each nametable byte gets its own 4 KB CHR bank and palette from ExRAM.
+    ExtendedAttributes,
+    Ram,
+    ReadOnlyRam,
+}
+
+/// How the background tile being fetched is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TileFetch {
+    Normal,
+    /// The tile comes from the vertical split, at this line of the split.
+    Split {
+        y: u16,
+    },
+    /// The tile takes its CHR bank and palette from this ExRAM byte.
+    ExtendedAttribute(u8),
+}
+
+/// MMC5 (mapper 5), also known as ExROM.
+///
+/// Besides 4 PRG and 4 CHR banking modes, it has 1 KB of ExRAM that can be used
+/// as a nametable or for extended attributes, a fill mode nametable, a vertical
+/// split, a scanline IRQ and an 8x8 multiplier.
+///
+/// The MMC5 has no idea where the PPU is, so it works it out from the reads
+/// the PPU makes: the two unused nametable fetches at the end of a scanline and
+/// the first one of the next are reads of the same address, which marks the
+/// start of a scanline. From there on, the dots are counted to tell sprite
+/// fetches from background fetches.
+pub(super) struct Mmc5 {
+    program_rom: Vec<u8>,
+    program_ram: Vec<u8>,
+    character_rom: Vec<u8>,
+    extended_ram: [u8; EXTENDED_RAM_SIZE],
+    program_mode: u8,
+    character_mode: u8,
+    program_ram_protect: [u8; 2],
+    extended_ram_mode: ExtendedRamMode,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    /// $5113-$5117, where $5113 is the PRG-RAM bank at $6000.
+    program_banks: [u8; 5],
+    /// $5120-$5127, used for sprites in 8x16 mode.
+    sprite_character_banks: [u16; 8],
+    /// $5128-$512B, used for the background in 8x16 mode.
+    background_character_banks: [u16; 4],
+    /// $5130, the upper bits of the CHR bank numbers.
+    character_bank_upper_bits: u8,
+    /// Whether the background banks were written after the sprite banks. In 8x8
+    /// mode and outside of rendering, the last written set is used for everything.
+    background_banks_written_last: bool,
+    sprites_8x16: bool,
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    irq_compare: u8,
+    irq_enabled: bool,
+    /// Acknowledged by reading $5204, which can't take `&mut self`.
+    irq_pending: Cell<bool>,
+    in_frame: bool,
+    scanline: u8,
+    /// The dot of the scanline, counted from the detected start of the scanline.
+    dot: u16,
+    last_read_address: u16,
+    matching_reads: u8,
+    idle_dots: u8,
+    tile_fetch: TileFetch,
+    multiplicand: u8,
+    multiplier: u8,
+    mirroring: Mirroring,
+    connections: Connections,
+}
+
+impl Mmc5 {
+    pub fn new(ines: Ines) -> Self {
+        let mut character_rom = ines.character_rom;
+        if character_rom.is_empty() {
+            character_rom.resize(KB * 8, 0);
+        }
+
+        Self {
+            program_rom: ines.program_rom,
+            program_ram: vec![0; PROGRAM_RAM_SIZE],
+            character_rom,
+            extended_ram: [0; EXTENDED_RAM_SIZE],
+            // At power on, every PRG bank is 8 KB and the last bank of the ROM
+            // is at $E000.
+            program_mode: 3,
+            character_mode: 0,
+            program_ram_protect: [0; 2],
+            extended_ram_mode: ExtendedRamMode::Nametable,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            program_banks: [0, 0, 0, 0, 0xFF],
+            sprite_character_banks: [0; 8],
+            background_character_banks: [0; 4],
+            character_bank_upper_bits: 0,
+            background_banks_written_last: false,
+            sprites_8x16: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: Cell::new(false),
+            in_frame: false,
+            scanline: 0,
+            dot: 0,
+            last_read_address: 0,
+            matching_reads: 0,
+            idle_dots: 0,
+            tile_fetch: TileFetch::Normal,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            mirroring: Mirroring::Mapped([0; 4]),
+            connections: Connections::default(),
+        }
+    }
+
+    /// Returns where the PRG address is mapped to, as whether it is ROM and the
+    /// offset into the ROM or RAM.
+    fn program_address(&self, address: u16) -> (bool, usize) {
+        let offset = address as usize % PROGRAM_BANK_SIZE;
+
+        if address < 0x8000 {
+            let bank = self.program_banks[0] as usize & 0x07;
+            return (false, bank * PROGRAM_BANK_SIZE + offset);
+        }
+
+        // The register and the size of the bank (in 8 KB banks) for each 8 KB slot.
+        let slot = (address as usize - 0x8000) / PROGRAM_BANK_SIZE;
+        let (register, size) = match (self.program_mode, slot) {
+            (0, _) => (4, 4),
+            (1, 0 | 1) | (2, 0 | 1) => (2, 2),
+            (1, _) => (4, 2),
+            (2, 2) => (3, 1),
+            (2, _) => (4, 1),
+            (_, slot) => (slot + 1, 1),
+        };
+
+        let value = self.program_banks[register];
+        // $E000 always has ROM.
+        let rom = register == 4 || value & PROGRAM_BANK_ROM != 0;
+        // Larger banks ignore the lower bits of the bank number.
+        let bank = ((value & !PROGRAM_BANK_ROM) as usize & !(size - 1)) | (slot % size);
+
+        match rom {
+            true => (
+                true,
+                (bank * PROGRAM_BANK_SIZE + offset) % self.program_rom.len(),
+            ),
+            false => (false, (bank & 0x07) * PROGRAM_BANK_SIZE + offset),
+        }
+    }
+
+    fn program_ram_writable(&self) -> bool {
+        self.program_ram_protect == PROGRAM_RAM_WRITABLE
+    }
+
+    fn nametable_source(&self, address: u16) -> NametableSource {
+        let slot = (address >> 10) & 0b11;
+
+        match (self.nametable_mapping >> (slot * 2)) & 0b11 {
+            2 => NametableSource::ExtendedRam,
+            3 => NametableSource::Fill,
+            page => NametableSource::Ciram(page),
+        }
+    }
+
+    fn update_mirroring(&mut self) {
+        let pages = [0, 1, 2, 3].map(|slot| match self.nametable_source(slot << 10) {
+            NametableSource::Ciram(page) => page,
+            _ => 0,
+        });
+        self.mirroring = Mirroring::Mapped(pages);
+
+        self.connections.set_mirroring(self.mirroring);
+    }
+
+    /// Looks up a pattern table address in one set of CHR banks, which are
+    /// 8, 4, 2 or 1 KB depending on the CHR mode.
+    fn character_address(&self, banks: [u16; 8], address: u16) -> usize {
+        let size = (CHARACTER_BANK_SIZE * 8) >> self.character_mode;
+        let address = address as usize & 0x1FFF;
+        // Larger banks are set by the last register of their range.
+        let register = (address / size + 1) * (8 >> self.character_mode) - 1;
+
+        (banks[register] as usize * size + address % size) % self.character_rom.len()
+    }
+
+    /// Whether the PPU is fetching sprite patterns right now, rather than the
+    /// background.
+    fn fetching_sprites(&self) -> bool {
+        matches!(self.dot, 257..=320)
+    }
+
+    fn rendering_background(&self) -> bool {
+        self.in_frame && !self.fetching_sprites()
+    }
+
+    /// Takes note of every read the PPU makes, to find the start of each
+    /// scanline and the end of the frame.
+    fn observe_read(&mut self, address: u16) {
+        self.idle_dots = 0;
+
+        match address >= 0x2000 && address == self.last_read_address {
+            true => self.matching_reads += 1,
+            false => self.matching_reads = 0,
+        }
+        self.last_read_address = address;
+
+        if self.matching_reads == 2 {
+            self.start_scanline();
+        }
+    }
+
+    fn start_scanline(&mut self) {
+        match self.in_frame {
+            true => {
+                self.scanline = self.scanline.wrapping_add(1);
+
+                if self.scanline == self.irq_compare {
+                    self.irq_pending.set(true);
+                }
+            }
+            false => {
+                self.in_frame = true;
+                self.scanline = 0;
+            }
+        }
+
+        // The third matching read is the first fetch of the scanline, on dot 1.
+        self.dot = 1;
+    }
+
+    /// The column of the background tile being fetched. The first two tiles of a
+    /// scanline are fetched at the end of the previous one.
+    fn tile_column(&self) -> (u16, bool) {
+        match self.dot {
+            321.. => ((self.dot - 321) / 8, true),
+            _ => ((self.dot - 1) / 8 + 2, false),
+        }
+    }
+
+    /// Decides how the background tile that starts with a nametable fetch at
+    /// `offset` is drawn.
+    fn start_tile_fetch(&mut self, offset: usize) {
+        let (column, next_scanline) = self.tile_column();
+        let threshold = (self.split_control & SPLIT_THRESHOLD) as u16;
+        let in_split = match self.split_control & SPLIT_RIGHT != 0 {
+            true => column >= threshold,
+            false => column < threshold,
+        };
+
+        self.tile_fetch = if self.split_control & SPLIT_ENABLE != 0
+            && in_split
+            && self.extended_ram_mode != ExtendedRamMode::Ram
+            && self.extended_ram_mode != ExtendedRamMode::ReadOnlyRam
+        {
+            let scanline = self.scanline as u16 + u16::from(next_scanline);
+            TileFetch::Split {
+                y: (self.split_scroll as u16 + scanline) % VISIBLE_SCANLINES,
+            }
+        } else if self.extended_ram_mode == ExtendedRamMode::ExtendedAttributes {
+            TileFetch::ExtendedAttribute(self.extended_ram[offset])
+        } else {
+            TileFetch::Normal
+        };
+    }
+
+    fn split_nametable_read(&self, y: u16, attribute: bool) -> u8 {
+        let (column, _) = self.tile_column();
+        let column = column as usize % 32;
+        let row = y as usize / 8;
+
+        match attribute {
+            false => self.extended_ram[row * 32 + column],
+            true => {
+                let byte = self.extended_ram[ATTRIBUTE_TABLE_OFFSET + (row / 4) * 8 + column / 4];
+                let shift = ((row & 0b10) << 1) | (column & 0b10);
+                ((byte >> shift) & 0b11) * 0x55
+            }
+        }
+    }
+
+    fn background_pattern_address(&self, address: u16) -> usize {
+        let bank_4k = |bank: usize| bank * KB * 4;
+
+        match self.tile_fetch {
+            TileFetch::Split { y } => {
+                let tile_row = (address as usize & 0x0FF8) | (y as usize & 0b111);
+                (bank_4k(self.split_bank as usize) + tile_row) % self.character_rom.len()
+            }
+            TileFetch::ExtendedAttribute(byte) => {
+                let bank =
+                    ((self.character_bank_upper_bits as usize) << 6) | (byte as usize & 0x3F);
+                (bank_4k(bank) + (address as usize & 0x0FFF)) % self.character_rom.len()
+            }
+            TileFetch::Normal => self.character_address(self.background_banks(), address),
+        }
+    }
+
+    /// The background banks repeat for both pattern tables.
+    fn background_banks(&self) -> [u16; 8] {
+        let banks = self.background_character_banks;
+        [0, 1, 2, 3, 0, 1, 2, 3].map(|register| banks[register])
+    }
+
+    fn product(&self) -> u16 {
+        self.multiplicand as u16 * self.multiplier as u16
+    }
+}
+
+impl ClockableMapper for Mmc5 {
+    type Cpu = Rc<RefCell<CpuContainer>>;
+    type Ppu = Rc<RefCell<Ppu>>;
+
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x5204 => {
+                let mut status = 0;
+                if self.irq_pending.replace(false) {
+                    status |= STATUS_IRQ_PENDING;
+                }
+                if self.in_frame {
+                    status |= STATUS_IN_FRAME;
+                }
+                status
+            }
+            0x5205 => self.product() as u8,
+            0x5206 => (self.product() >> 8) as u8,
+            0x5C00..=0x5FFF
+                if matches!(
+                    self.extended_ram_mode,
+                    ExtendedRamMode::Ram | ExtendedRamMode::ReadOnlyRam
+                ) =>
+            {
+                self.extended_ram[address as usize - 0x5C00]
+            }
+            0x6000..=0xFFFF => match self.program_address(address) {
+                (true, address) => self.program_rom[address],
+                (false, address) => self.program_ram[address],
+            },
+            _ => open_bus(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, byte: u8) {
+        match address {
+            0x5100 => self.program_mode = byte & 0b11,
+            0x5101 => self.character_mode = byte & 0b11,
+            0x5102 | 0x5103 => self.program_ram_protect[address as usize - 0x5102] = byte & 0b11,
+            0x5104 => {
+                self.extended_ram_mode = match byte & 0b11 {
+                    0 => ExtendedRamMode::Nametable,
+                    1 => ExtendedRamMode::ExtendedAttributes,
+                    2 => ExtendedRamMode::Ram,
+                    _ => ExtendedRamMode::ReadOnlyRam,
+                }
+            }
+            0x5105 => {
+                self.nametable_mapping = byte;
+                self.update_mirroring();
+            }
+            0x5106 => self.fill_tile = byte,
+            0x5107 => self.fill_attribute = byte & 0b11,
+            0x5113..=0x5117 => self.program_banks[address as usize - 0x5113] = byte,
+            0x5120..=0x5127 => {
+                self.sprite_character_banks[address as usize - 0x5120] =
+                    ((self.character_bank_upper_bits as u16) << 8) | byte as u16;
+                self.background_banks_written_last = false;
+            }
+            0x5128..=0x512B => {
+                self.background_character_banks[address as usize - 0x5128] =
+                    ((self.character_bank_upper_bits as u16) << 8) | byte as u16;
+                self.background_banks_written_last = true;
+            }
+            0x5130 => self.character_bank_upper_bits = byte & 0b11,
+            0x5200 => self.split_control = byte,
+            0x5201 => self.split_scroll = byte,
+            0x5202 => self.split_bank = byte,
+            0x5203 => self.irq_compare = byte,
+            0x5204 => self.irq_enabled = byte & IRQ_ENABLE != 0,
+            0x5205 => self.multiplicand = byte,
+            0x5206 => self.multiplier = byte,
+            0x5C00..=0x5FFF => {
+                let index = address as usize - 0x5C00;
+
+                match self.extended_ram_mode {
+                    // While the PPU uses ExRAM, only writes during rendering get through.
+                    ExtendedRamMode::Nametable | ExtendedRamMode::ExtendedAttributes => {
+                        self.extended_ram[index] = if self.in_frame { byte } else { 0 };
+                    }
+                    ExtendedRamMode::Ram => self.extended_ram[index] = byte,
+                    ExtendedRamMode::ReadOnlyRam => {}
+                }
+            }
+            0x6000..=0xFFFF => {
+                if let (false, address) = self.program_address(address) {
+                    if self.program_ram_writable() {
+                        self.program_ram[address] = byte;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.observe_read(address);
+
+        // Extended attributes and the split work in 8x8 mode as well.
+        let address = if self.rendering_background()
+            && (self.sprites_8x16 || self.tile_fetch != TileFetch::Normal)
+        {
+            self.background_pattern_address(address)
+        } else if self.sprites_8x16 && self.in_frame {
+            self.character_address(self.sprite_character_banks, address)
+        } else if self.background_banks_written_last {
+            self.character_address(self.background_banks(), address)
+        } else {
+            self.character_address(self.sprite_character_banks, address)
+        };
+
+        self.character_rom[address]
+    }
+
+    fn ppu_write(&mut self, _address: u16, _byte: u8) {}
+
+    fn read_nametable(&mut self, address: u16) -> Option<u8> {
+        self.observe_read(address);
+
+        let offset = address as usize & 0x03FF;
+        let attribute = offset >= ATTRIBUTE_TABLE_OFFSET;
+
+        if self.rendering_background() {
+            if !attribute {
+                self.start_tile_fetch(offset);
+            }
+
+            match self.tile_fetch {
+                TileFetch::Split { y } => return Some(self.split_nametable_read(y, attribute)),
+                TileFetch::ExtendedAttribute(byte) if attribute => return Some((byte >> 6) * 0x55),
+                _ => {}
+            }
+        }
+
+        match self.nametable_source(address) {
+            NametableSource::Ciram(_) => None,
+            NametableSource::ExtendedRam => Some(match self.extended_ram_mode {
+                ExtendedRamMode::Nametable | ExtendedRamMode::ExtendedAttributes => {
+                    self.extended_ram[offset]
+                }
+                _ => 0,
+            }),
+            NametableSource::Fill if attribute => Some(self.fill_attribute * 0x55),
+            NametableSource::Fill => Some(self.fill_tile),
+        }
+    }
+
+    fn write_nametable(&mut self, address: u16, byte: u8) -> bool {
+        match self.nametable_source(address) {
+            NametableSource::Ciram(_) => false,
+            NametableSource::ExtendedRam => {
+                if matches!(
+                    self.extended_ram_mode,
+                    ExtendedRamMode::Nametable | ExtendedRamMode::ExtendedAttributes
+                ) {
+                    self.extended_ram[address as usize & 0x03FF] = byte;
+                }
+                true
+            }
+            NametableSource::Fill => true,
+        }
+    }
+
+    fn ppu_register_write(&mut self, address: u16, byte: u8) {
+        if address == PPUCTRL {
+            self.sprites_8x16 = byte & PPUCTRL_SPRITE_SIZE != 0;
+        }
+    }
+
+    fn ppu_bus(&mut self, _address: u16) {
+        // The dots only matter within a scanline, and outside of the frame there
+        // is no scanline start to count from.
+        if self.in_frame {
+            self.dot += 1;
+        }
+        self.idle_dots = self.idle_dots.saturating_add(1);
+
+        if self.idle_dots > IDLE_DOTS {
+            self.in_frame = false;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_enabled && self.irq_pending.get()
+    }
+
+    fn clock(&mut self) {}
+
+    fn initialize(&mut self, cpu: Self::Cpu, ppu: Self::Ppu) {
+        self.connections.connect(cpu, ppu, self.mirroring);
+    }
+
+    fn initialized(&self) -> bool {
+        self.connections.connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::numbered_rom;
+    use super::*;
+
+    /// Creates an MMC5 board with 128 KB of PRG-ROM and 256 KB of CHR-ROM, where
+    /// every 8 KB PRG bank and 1 KB CHR bank is filled with its own number.
+    fn mmc5() -> Mmc5 {
+        Mmc5::new(numbered_rom(
+            5,
+            PROGRAM_BANK_SIZE,
+            16,
+            CHARACTER_BANK_SIZE,
+            256,
+        ))
+    }
+
+    fn read_program_banks(mmc5: &Mmc5) -> [u8; 4] {
+        [0x8000, 0xA000, 0xC000, 0xE000].map(|address| mmc5.read(address))
+    }
+
+    /// Makes the reads the PPU makes on a rendered scanline, with the background
+    /// at $0000 and the sprites at $1000. `on_fetch` gets the dot, the address
+    /// and the result of each read, where nametable reads left to CIRAM are `None`.
+    fn render_scanline(mmc5: &mut Mmc5, mut on_fetch: impl FnMut(u16, u16, Option<u8>)) {
+        for dot in 0..341 {
+            // Each scanline fetches tiles 2 to 33, and then 0 and 1 for the next one.
+            let tile = match dot {
+                321.. => (dot - 321) / 8,
+                _ => (dot.max(1) - 1) / 8 + 2,
+            };
+
+            let address = match dot {
+                1..=256 | 321..=336 => match (dot - 1) % 8 {
+                    0 => Some(0x2000 + tile),
+                    2 => Some(0x23C0 + tile / 4),
+                    4 => Some(0x0000),
+                    6 => Some(0x0008),
+                    _ => None,
+                },
+                257..=320 if matches!((dot - 257) % 8, 4 | 6) => Some(0x1FF0),
+                337 | 339 => Some(0x2002),
+                _ => None,
+            };
+
+            if let Some(address) = address {
+                let byte = match address >= 0x2000 {
+                    true => mmc5.read_nametable(address),
+                    false => Some(mmc5.ppu_read(address)),
+                };
+                on_fetch(dot, address, byte);
+            }
+
+            mmc5.ppu_bus(0);
+        }
+    }
+
+    fn render_scanlines(mmc5: &mut Mmc5, scanlines: usize) {
+        for _ in 0..scanlines {
+            render_scanline(mmc5, |_, _, _| {});
+        }
+    }
+
+    fn end_frame(mmc5: &mut Mmc5) {
+        for _ in 0..=IDLE_DOTS {
+            mmc5.ppu_bus(0);
+        }
+    }
+
+    #[test]
+    fn program_banks_follow_the_program_mode() {
+        let mut mmc5 = mmc5();
+        assert_eq!(mmc5.read(0xE000), 15);
+
+        for (register, bank) in (0x5114..=0x5117).zip([0x81, 0x83, 0x85, 0x87]) {
+            mmc5.write(register, bank);
+        }
+        assert_eq!(read_program_banks(&mmc5), [1, 3, 5, 7]);
+
+        mmc5.write(0x5100, 2);
+        assert_eq!(read_program_banks(&mmc5), [2, 3, 5, 7]);
+
+        mmc5.write(0x5100, 1);
+        assert_eq!(read_program_banks(&mmc5), [2, 3, 6, 7]);
+
+        mmc5.write(0x5100, 0);
+        assert_eq!(read_program_banks(&mmc5), [4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn program_ram_is_banked_and_write_protected() {
+        let mut mmc5 = mmc5();
+        mmc5.write(0x5113, 1);
+        mmc5.write(0x6000, 0x12);
+        assert_eq!(mmc5.read(0x6000), 0x00);
+
+        mmc5.write(0x5102, 0b10);
+        mmc5.write(0x5103, 0b01);
+        mmc5.write(0x6000, 0x12);
+        assert_eq!(mmc5.read(0x6000), 0x12);
+
+        // RAM can be mapped into $8000-$DFFF as well.
+        mmc5.write(0x5114, 0x01);
+        assert_eq!(mmc5.read(0x8000), 0x12);
+        mmc5.write(0x5113, 0);
+        assert_eq!(mmc5.read(0x6000), 0x00);
+    }
+
+    #[test]
+    fn character_banks_follow_the_character_mode() {
+        let mut mmc5 = mmc5();
+        for (register, bank) in (0x5120..=0x5127).zip(10..) {
+            mmc5.write(register, bank);
+        }
+        let read_banks = |mmc5: &mut Mmc5| {
+            (0..8)
+                .map(|slot| mmc5.ppu_read(slot * 0x400))
+                .collect::<Vec<_>>()
+        };
+
+        mmc5.write(0x5101, 3);
+        assert_eq!(read_banks(&mut mmc5), [10, 11, 12, 13, 14, 15, 16, 17]);
+
+        // The 2 KB banks are $5121, $5123, $5125 and $5127.
+        mmc5.write(0x5101, 2);
+        assert_eq!(read_banks(&mut mmc5), [22, 23, 26, 27, 30, 31, 34, 35]);
+
+        mmc5.write(0x5101, 0);
+        assert_eq!(read_banks(&mut mmc5), (136..144).collect::<Vec<_>>());
+
+        // The upper bits are taken when a bank is written.
+        mmc5.write(0x5101, 3);
+        mmc5.write(0x5130, 0b01);
+        mmc5.write(0x5120, 0x02);
+        mmc5.write(0x5130, 0b00);
+        assert_eq!(mmc5.sprite_character_banks[0], 0x102);
+    }
+
+    #[test]
+    fn sprites_and_background_use_their_own_banks_in_8x16_mode() {
+        let mut mmc5 = mmc5();
+        mmc5.write(0x5101, 3);
+        mmc5.write(0x5127, 7);
+        mmc5.write(0x5128, 40);
+        mmc5.ppu_register_write(PPUCTRL, PPUCTRL_SPRITE_SIZE);
+
+        // Outside of rendering, the last written set is used.
+        assert_eq!(mmc5.ppu_read(0x0000), 40);
+        assert_eq!(mmc5.ppu_read(0x1000), 40);
+
+        render_scanlines(&mut mmc5, 1);
+        let mut pattern_fetches = Vec::new();
+        render_scanline(&mut mmc5, |dot, address, byte| {
+            if address < 0x2000 {
+                pattern_fetches.push((matches!(dot, 257..=320), byte.unwrap()));
+            }
+        });
+
+        // Sprite patterns are fetched on dots 257-320, the background on the others.
+        pattern_fetches.dedup();
+        assert_eq!(pattern_fetches, [(false, 40), (true, 7), (false, 40)]);
+    }
+
+    #[test]
+    fn nametables_can_come_from_ciram_extended_ram_and_fill_mode() {
+        let mut mmc5 = mmc5();
+        // CIRAM page 1, CIRAM page 0, ExRAM and fill mode.
+        mmc5.write(0x5105, 0b11_10_00_01);
+        assert_eq!(mmc5.mirroring, Mirroring::Mapped([1, 0, 0, 0]));
+
+        mmc5.write(0x5106, 0x42);
+        mmc5.write(0x5107, 0b10);
+        mmc5.write(0x5104, 2);
+        mmc5.write(0x5C05, 0x99);
+        mmc5.write(0x5104, 0);
+
+        assert_eq!(mmc5.read_nametable(0x2005), None);
+        assert_eq!(mmc5.read_nametable(0x2805), Some(0x99));
+        assert_eq!(mmc5.read_nametable(0x2C05), Some(0x42));
+        assert_eq!(mmc5.read_nametable(0x2FC0), Some(0xAA));
+
+        assert!(mmc5.write_nametable(0x2806, 0x77));
+        assert_eq!(mmc5.read_nametable(0x2806), Some(0x77));
+        assert!(!mmc5.write_nametable(0x2406, 0x77));
+    }
+
+    #[test]
+    fn extended_ram_is_only_readable_as_ram_and_writable_during_rendering() {
+        let mut mmc5 = mmc5();
+        mmc5.write(0x5C00, 0x12);
+        assert_eq!(mmc5.read(0x5C00), 0x5C);
+
+        mmc5.write(0x5104, 2);
+        assert_eq!(mmc5.read(0x5C00), 0x00);
+        mmc5.write(0x5C00, 0x34);
+        assert_eq!(mmc5.read(0x5C00), 0x34);
+
+        mmc5.write(0x5104, 3);
+        mmc5.write(0x5C00, 0x56);
+        assert_eq!(mmc5.read(0x5C00), 0x34);
+    }
+
+    #[test]
+    fn extended_attributes_pick_the_bank_and_palette_of_each_tile() {
+        let mut mmc5 = mmc5();
+        mmc5.write(0x5104, 2);
+        // Tile 2 takes 4 KB bank 5 and palette 3.
+        mmc5.write(0x5C02, 0b11_000101);
+        mmc5.write(0x5104, 1);
+
+        render_scanlines(&mut mmc5, 1);
+        let mut fetches = Vec::new();
+        render_scanline(&mut mmc5, |dot, address, byte| {
+            fetches.push((dot, address, byte));
+        });
+
+        // The first tile fetched on a scanline is tile 2.
+        assert_eq!(
+            fetches[..4],
+            [
+                (1, 0x2002, None),
+                (3, 0x23C0, Some(0xFF)),
+                (5, 0x0000, Some(20)),
+                (7, 0x0008, Some(20)),
+            ]
+        );
+    }
+
+    #[test]
+    fn vertical_split_replaces_the_tiles_left_of_the_threshold() {
+        let mut mmc5 = mmc5();
+        mmc5.write(0x5104, 2);
+        for column in 0..32 {
+            mmc5.write(0x5C00 + column, 0x80 + column as u8);
+        }
+        mmc5.write(0x5104, 0);
+        mmc5.write(0x5200, SPLIT_ENABLE | 3);
+        mmc5.write(0x5202, 2);
+        // The CIRAM nametables are left to the PPU, so they read as `None`.
+        render_scanlines(&mut mmc5, 1);
+
+        let mut fetches = Vec::new();
+        render_scanline(&mut mmc5, |dot, address, byte| {
+            fetches.push((dot, address, byte));
+        });
+
+        // Tiles 0 and 1 of the next scanline are fetched at the end of this one.
+        let split_tiles: Vec<_> = fetches
+            .iter()
+            .filter(|(dot, address, _)| *dot <= 336 && (0x2000..0x23C0).contains(address))
+            .filter_map(|&(dot, _, byte)| Some((dot, byte?)))
+            .collect();
+        assert_eq!(split_tiles, [(1, 0x82), (321, 0x80), (329, 0x81)]);
+
+        // The split takes its patterns from its own 4 KB bank.
+        assert!(fetches.contains(&(5, 0x0000, Some(8))));
+    }
+
+    #[test]
+    fn scanline_irq_fires_on_the_compared_scanline() {
+        let mut mmc5 = mmc5();
+        mmc5.write(0x5203, 3);
+        mmc5.write(0x5204, IRQ_ENABLE);
+
+        // The frame starts at the end of the pre-render scanline, so this renders
+        // up to scanline 2.
+        render_scanlines(&mut mmc5, 4);
+        assert!(!mmc5.irq());
+        assert_eq!(mmc5.read(0x5204), STATUS_IN_FRAME);
+
+        render_scanlines(&mut mmc5, 1);
+        assert!(mmc5.irq());
+        assert_eq!(mmc5.read(0x5204), STATUS_IRQ_PENDING | STATUS_IN_FRAME);
+        assert!(!mmc5.irq());
+
+        end_frame(&mut mmc5);
+        assert_eq!(mmc5.read(0x5204), 0);
+    }
+
+    #[test]
+    fn a_frame_with_rendering_off_keeps_the_dot_from_overflowing() {
+        let mut mmc5 = mmc5();
+
+        // 262 scanlines of 341 dots without a single PPU read.
+        for _ in 0..262 * 341 {
+            mmc5.ppu_bus(0);
+        }
+        assert!(!mmc5.in_frame);
+
+        render_scanlines(&mut mmc5, 2);
+        assert!(mmc5.in_frame);
+        assert_eq!(mmc5.dot, 341);
+    }
+
+    #[test]
+    fn multiplier_returns_the_16_bit_product() {
+        let mut mmc5 = mmc5();
+        assert_eq!((mmc5.read(0x5205), mmc5.read(0x5206)), (0x01, 0xFE));
+
+        mmc5.write(0x5205, 200);
+        mmc5.write(0x5206, 3);
+        assert_eq!((mmc5.read(0x5205), mmc5.read(0x5206)), (0x58, 0x02));
+    }
+}
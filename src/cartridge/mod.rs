@@ -1,10 +1,105 @@
 use crate::cpu::CpuContainer;
+use crate::ines::{Header, NametableArrangement};
 use crate::{ines::Ines, ppu::Ppu};
 use std::cell::RefCell;
 use std::rc::Rc;
 
+mod axrom;
+mod cnrom;
+mod gxrom;
+mod mmc1;
+mod mmc2;
+mod mmc3;
+mod mmc5;
+mod uxrom;
+
 const KB: usize = 1024;
 
+/// The byte read from an address no chip answers.
+pub(crate) fn open_bus(address: u16) -> u8 {
+    // Nothing drives the bus, so the high byte of the address is left on it.
+    (address >> 8) as u8
+}
+
+/// The parts of the console a board is plugged into.
+#[derive(Default)]
+struct Connections {
+    cpu: Option<Rc<RefCell<CpuContainer>>>,
+    ppu: Option<Rc<RefCell<Ppu>>>,
+}
+
+impl Connections {
+    fn connect(
+        &mut self,
+        cpu: Rc<RefCell<CpuContainer>>,
+        ppu: Rc<RefCell<Ppu>>,
+        mirroring: Mirroring,
+    ) {
+        ppu.borrow_mut().set_mirroring(mirroring);
+
+        self.cpu = Some(cpu);
+        self.ppu = Some(ppu);
+    }
+
+    fn connected(&self) -> bool {
+        self.ppu.is_some()
+    }
+
+    /// Rewires the nametables, for boards that switch the mirroring.
+    fn set_mirroring(&self, mirroring: Mirroring) {
+        if let Some(ppu) = &self.ppu {
+            ppu.borrow_mut().set_mirroring(mirroring);
+        }
+    }
+}
+
+/// How the cartridge wires the nametable addresses ($2000-$2FFF) onto the 2 KB of
+/// CIRAM in the console (or onto the extra 2 KB of VRAM of four-screen boards).
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum Mirroring {
+    /// $2000 = $2400 and $2800 = $2C00, for vertically scrolling games.
+    #[default]
+    Horizontal,
+    /// $2000 = $2800 and $2400 = $2C00, for horizontally scrolling games.
+    Vertical,
+    /// All four nametables show the first 1 KB of CIRAM.
+    SingleScreenLower,
+    /// All four nametables show the second 1 KB of CIRAM.
+    SingleScreenUpper,
+    /// Every nametable is backed by its own 1 KB.
+    FourScreen,
+    /// Each nametable shows the 1 KB of CIRAM picked by the board, for mappers
+    /// like the MMC5 that can map every nametable on its own.
+    Mapped([u8; 4]),
+}
+
+impl Mirroring {
+    pub fn from_header(header: &Header) -> Self {
+        if header.alternative_nametable_layout {
+            return Self::FourScreen;
+        }
+
+        match header.nametable_arrangement {
+            NametableArrangement::VerticalArrangement => Self::Horizontal,
+            NametableArrangement::HorizontalArrangement => Self::Vertical,
+        }
+    }
+
+    /// Returns the offset into nametable memory that a nametable address maps to.
+    pub fn nametable_index(self, address: u16) -> usize {
+        let address = address as usize & 0x0FFF;
+
+        match self {
+            Self::Horizontal => ((address >> 1) & 0x0400) | (address & 0x03FF),
+            Self::Vertical => address & 0x07FF,
+            Self::SingleScreenLower => address & 0x03FF,
+            Self::SingleScreenUpper => 0x0400 | (address & 0x03FF),
+            Self::FourScreen => address,
+            Self::Mapped(pages) => ((pages[address >> 10] as usize & 1) << 10) | (address & 0x03FF),
+        }
+    }
+}
+
 pub trait ClockableMapper {
     type Cpu;
     type Ppu;
@@ -13,6 +108,45 @@ pub trait ClockableMapper {
 
     fn write(&mut self, address: u16, byte: u8);
 
+    /// Returns the byte that a write to PRG-ROM leaves on the bus. Discrete boards
+    /// don't keep the ROM off the bus during writes, so the ROM keeps driving the
+    /// byte at the address, and the written byte is ANDed with it (a bus conflict).
+    fn bus_conflict(&self, address: u16, byte: u8) -> u8 {
+        byte & self.read(address)
+    }
+
+    /// Reads from the pattern tables ($0000-$1FFF) on the PPU bus.
+    fn ppu_read(&mut self, address: u16) -> u8;
+
+    /// Writes to the pattern tables ($0000-$1FFF) on the PPU bus. Boards with
+    /// CHR-ROM ignore these.
+    fn ppu_write(&mut self, address: u16, byte: u8);
+
+    /// Boards with their own nametable memory return `Some` to take a nametable
+    /// read ($2000-$2FFF) away from CIRAM.
+    fn read_nametable(&mut self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    /// Returns `true` if the board took a nametable write ($2000-$2FFF) away from CIRAM.
+    fn write_nametable(&mut self, _address: u16, _byte: u8) -> bool {
+        false
+    }
+
+    /// Called on every PPU dot with the address on the PPU bus. While rendering,
+    /// that is the last address the PPU fetched from, otherwise it is `v`. Boards
+    /// that watch the address lines, like the MMC3 counting rises of A12, use this.
+    fn ppu_bus(&mut self, _address: u16) {}
+
+    /// Called when the CPU writes to a PPU register ($2000-$2007), for boards that
+    /// snoop those writes, like the MMC5 watching the sprite size in PPUCTRL.
+    fn ppu_register_write(&mut self, _address: u16, _byte: u8) {}
+
+    /// Returns `true` while the board holds the CPU IRQ line low.
+    fn irq(&self) -> bool {
+        false
+    }
+
     fn clock(&mut self);
 
     /// Initialize the APU.
@@ -50,6 +184,34 @@ impl Cartridge {
         self.mapper.write(address, byte)
     }
 
+    pub fn ppu_read(&mut self, address: u16) -> u8 {
+        self.mapper.ppu_read(address)
+    }
+
+    pub fn ppu_write(&mut self, address: u16, byte: u8) {
+        self.mapper.ppu_write(address, byte)
+    }
+
+    pub fn read_nametable(&mut self, address: u16) -> Option<u8> {
+        self.mapper.read_nametable(address)
+    }
+
+    pub fn write_nametable(&mut self, address: u16, byte: u8) -> bool {
+        self.mapper.write_nametable(address, byte)
+    }
+
+    pub fn ppu_bus(&mut self, address: u16) {
+        self.mapper.ppu_bus(address);
+    }
+
+    pub fn ppu_register_write(&mut self, address: u16, byte: u8) {
+        self.mapper.ppu_register_write(address, byte);
+    }
+
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
     pub fn clock(&mut self) {
         self.mapper.clock();
     }
@@ -57,23 +219,23 @@ impl Cartridge {
 
 struct Nrom {
     program_rom: [u8; KB * 32],
-    character_rom: [u8; KB * 32], // Can store up to 32kb of character rom, but we can use less as well
-    cpu: Option<Rc<RefCell<CpuContainer>>>,
-    ppu: Option<Rc<RefCell<Ppu>>>,
-    initialized: bool,
+    /// 8 KB of CHR-ROM, or CHR-RAM when the header has no CHR-ROM.
+    character_memory: Vec<u8>,
+    has_character_ram: bool,
+    mirroring: Mirroring,
+    connections: Connections,
 }
 
 impl Nrom {
     pub fn new(mut ines: Ines) -> Self {
         let mut program_rom = [0; KB * 32];
-        let mut character_rom = [0; KB * 32];
 
-        //character_rom.copy_from_slice(&ines.character_rom);
-        for (dest, src) in character_rom.iter_mut().zip(&ines.character_rom) {
-            *dest = *src;
-        }
+        let has_character_ram = ines.header.character_rom_size_multiplier == 0;
+        let mut character_memory = ines.character_rom.clone();
+        character_memory.resize(KB * 8, 0);
 
         let is_mirrored = program_rom.len() != ines.program_rom.len();
+        let mirroring = Mirroring::from_header(&ines.header);
 
         Self {
             program_rom: match is_mirrored {
@@ -88,10 +250,10 @@ impl Nrom {
                     program_rom
                 }
             },
-            character_rom,
-            cpu: None,
-            ppu: None,
-            initialized: false,
+            character_memory,
+            has_character_ram,
+            mirroring,
+            connections: Connections::default(),
         }
     }
 }
@@ -101,11 +263,24 @@ impl ClockableMapper for Nrom {
     type Ppu = Rc<RefCell<Ppu>>;
 
     fn read(&self, address: u16) -> u8 {
-        self.program_rom[address as usize - 0x8000]
+        match address {
+            0x8000..=0xFFFF => self.program_rom[address as usize - 0x8000],
+            _ => open_bus(address),
+        }
+    }
+
+    fn write(&mut self, _address: u16, _byte: u8) {
+        // NROM has no registers, and its PRG-ROM can't be written.
     }
 
-    fn write(&mut self, address: u16, byte: u8) {
-        self.program_rom[address as usize - 0x8000] = byte;
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.character_memory[address as usize & 0x1FFF]
+    }
+
+    fn ppu_write(&mut self, address: u16, byte: u8) {
+        if self.has_character_ram {
+            self.character_memory[address as usize & 0x1FFF] = byte;
+        }
     }
 
     fn clock(&mut self) {
@@ -113,13 +288,11 @@ impl ClockableMapper for Nrom {
     }
 
     fn initialize(&mut self, cpu: Self::Cpu, ppu: Self::Ppu) {
-        self.cpu = Some(cpu);
-        self.ppu = Some(ppu);
-        self.initialized = true;
+        self.connections.connect(cpu, ppu, self.mirroring);
     }
 
     fn initialized(&self) -> bool {
-        self.initialized
+        self.connections.connected()
     }
 }
 
@@ -128,6 +301,58 @@ fn select_mapper(
 ) -> Box<dyn ClockableMapper<Cpu = Rc<RefCell<CpuContainer>>, Ppu = Rc<RefCell<Ppu>>>> {
     match ines.header.mapper_number {
         0 => Box::new(Nrom::new(ines)),
+        1 => Box::new(mmc1::Mmc1::new(ines)),
+        2 => Box::new(uxrom::Uxrom::new(ines)),
+        3 => Box::new(cnrom::Cnrom::new(ines)),
+        4 => Box::new(mmc3::Mmc3::new(ines)),
+        5 => Box::new(mmc5::Mmc5::new(ines)),
+        7 => Box::new(axrom::Axrom::new(ines)),
+        9 | 10 => Box::new(mmc2::Mmc2::new(ines)),
+        66 => Box::new(gxrom::Gxrom::new(ines)),
         _ => panic!("Mapper not implemented"),
     }
 }
+
+/// Creates a ROM for the mapper where every PRG and CHR bank is filled with its
+/// own number, so tests can tell which bank a read went to.
+#[cfg(test)]
+fn numbered_rom(
+    mapper_number: u8,
+    program_bank_size: usize,
+    program_banks: usize,
+    character_bank_size: usize,
+    character_banks: usize,
+) -> Ines {
+    let program_rom: Vec<u8> = (0..program_banks)
+        .flat_map(|bank| vec![bank as u8; program_bank_size])
+        .collect();
+    let character_rom: Vec<u8> = (0..character_banks)
+        .flat_map(|bank| vec![bank as u8; character_bank_size])
+        .collect();
+
+    Ines {
+        header: Header {
+            program_rom_size_multiplier: (program_rom.len() / (KB * 16)) as u8,
+            character_rom_size_multiplier: (character_rom.len() / (KB * 8)) as u8,
+            mapper_number,
+            ..Header::default()
+        },
+        program_rom,
+        character_rom,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nrom_ignores_writes_and_leaves_open_bus_below_the_rom() {
+        let mut nrom = Nrom::new(numbered_rom(0, KB * 16, 2, KB * 8, 1));
+
+        nrom.write(0x6000, 0x80);
+        nrom.write(0xC000, 0x80);
+        assert_eq!(nrom.read(0x6000), 0x60);
+        assert_eq!(nrom.read(0xC000), 1);
+    }
+}
@@ -0,0 +1,111 @@
+use super::{open_bus, ClockableMapper, Connections, Mirroring, KB};
+use crate::cpu::CpuContainer;
+use crate::ines::Ines;
+use crate::ppu::Ppu;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const PROGRAM_BANK_SIZE: usize = KB * 16;
+
+/// UxROM (mapper 2). Writing to $8000-$FFFF switches the 16 KB PRG bank at
+/// $8000, and the last bank stays fixed at $C000. The board has 8 KB of CHR-RAM.
+/// Writes have bus conflicts.
+pub(super) struct Uxrom {
+    program_rom: Vec<u8>,
+    character_ram: [u8; KB * 8],
+    program_bank: u8,
+    mirroring: Mirroring,
+    connections: Connections,
+}
+
+impl Uxrom {
+    pub fn new(ines: Ines) -> Self {
+        Self {
+            mirroring: Mirroring::from_header(&ines.header),
+            program_rom: ines.program_rom,
+            character_ram: [0; KB * 8],
+            program_bank: 0,
+            connections: Connections::default(),
+        }
+    }
+
+    fn program_banks(&self) -> usize {
+        (self.program_rom.len() / PROGRAM_BANK_SIZE).max(1)
+    }
+}
+
+impl ClockableMapper for Uxrom {
+    type Cpu = Rc<RefCell<CpuContainer>>;
+    type Ppu = Rc<RefCell<Ppu>>;
+
+    fn read(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x8000..=0xBFFF => self.program_bank as usize % self.program_banks(),
+            0xC000..=0xFFFF => self.program_banks() - 1,
+            _ => return open_bus(address),
+        };
+
+        self.program_rom[bank * PROGRAM_BANK_SIZE + address as usize % PROGRAM_BANK_SIZE]
+    }
+
+    fn write(&mut self, address: u16, byte: u8) {
+        if address >= 0x8000 {
+            self.program_bank = self.bus_conflict(address, byte);
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.character_ram[address as usize & 0x1FFF]
+    }
+
+    fn ppu_write(&mut self, address: u16, byte: u8) {
+        self.character_ram[address as usize & 0x1FFF] = byte;
+    }
+
+    fn clock(&mut self) {}
+
+    fn initialize(&mut self, cpu: Self::Cpu, ppu: Self::Ppu) {
+        self.connections.connect(cpu, ppu, self.mirroring);
+    }
+
+    fn initialized(&self) -> bool {
+        self.connections.connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::numbered_rom;
+    use super::*;
+
+    fn uxrom() -> Uxrom {
+        let mut ines = numbered_rom(2, PROGRAM_BANK_SIZE, 8, 0, 0);
+        // A bank number table, like games use to avoid bus conflicts.
+        ines.program_rom[0x3FF0..0x3FF8].copy_from_slice(&[0, 1, 2, 3, 4, 5, 6, 7]);
+
+        Uxrom::new(ines)
+    }
+
+    #[test]
+    fn switches_the_lower_bank_and_fixes_the_last() {
+        let mut uxrom = uxrom();
+        assert_eq!((uxrom.read(0x8000), uxrom.read(0xFFFF)), (0, 7));
+
+        // Bank 0 ends up at $BFF0-$BFF7 with the table.
+        uxrom.write(0xBFF5, 5);
+        assert_eq!((uxrom.read(0x8000), uxrom.read(0xC000)), (5, 7));
+    }
+
+    #[test]
+    fn writes_conflict_with_the_rom() {
+        let mut uxrom = uxrom();
+
+        // The fixed bank is filled with 7, and 6 & 7 = 6.
+        uxrom.write(0xC000, 0xFE);
+        assert_eq!(uxrom.read(0x8000), 6);
+
+        // Bank 6 is filled with 6, and 3 & 6 = 2.
+        uxrom.write(0x8000, 3);
+        assert_eq!(uxrom.read(0x8000), 2);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::controller::Buttons;
+use minifb::Key;
+use serde::Deserialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// The keys that can be bound, by the names used in the config file.
+const KEY_NAMES: [(&str, Key); 105] = [
+    ("0", Key::Key0),
+    ("1", Key::Key1),
+    ("2", Key::Key2),
+    ("3", Key::Key3),
+    ("4", Key::Key4),
+    ("5", Key::Key5),
+    ("6", Key::Key6),
+    ("7", Key::Key7),
+    ("8", Key::Key8),
+    ("9", Key::Key9),
+    ("A", Key::A),
+    ("B", Key::B),
+    ("C", Key::C),
+    ("D", Key::D),
+    ("E", Key::E),
+    ("F", Key::F),
+    ("G", Key::G),
+    ("H", Key::H),
+    ("I", Key::I),
+    ("J", Key::J),
+    ("K", Key::K),
+    ("L", Key::L),
+    ("M", Key::M),
+    ("N", Key::N),
+    ("O", Key::O),
+    ("P", Key::P),
+    ("Q", Key::Q),
+    ("R", Key::R),
+    ("S", Key::S),
+    ("T", Key::T),
+    ("U", Key::U),
+    ("V", Key::V),
+    ("W", Key::W),
+    ("X", Key::X),
+    ("Y", Key::Y),
+    ("Z", Key::Z),
+    ("F1", Key::F1),
+    ("F2", Key::F2),
+    ("F3", Key::F3),
+    ("F4", Key::F4),
+    ("F5", Key::F5),
+    ("F6", Key::F6),
+    ("F7", Key::F7),
+    ("F8", Key::F8),
+    ("F9", Key::F9),
+    ("F10", Key::F10),
+    ("F11", Key::F11),
+    ("F12", Key::F12),
+    ("F13", Key::F13),
+    ("F14", Key::F14),
+    ("F15", Key::F15),
+    ("Down", Key::Down),
+    ("Left", Key::Left),
+    ("Right", Key::Right),
+    ("Up", Key::Up),
+    ("Apostrophe", Key::Apostrophe),
+    ("Backquote", Key::Backquote),
+    ("Backslash", Key::Backslash),
+    ("Comma", Key::Comma),
+    ("Equal", Key::Equal),
+    ("LeftBracket", Key::LeftBracket),
+    ("Minus", Key::Minus),
+    ("Period", Key::Period),
+    ("RightBracket", Key::RightBracket),
+    ("Semicolon", Key::Semicolon),
+    ("Slash", Key::Slash),
+    ("Backspace", Key::Backspace),
+    ("Delete", Key::Delete),
+    ("End", Key::End),
+    ("Enter", Key::Enter),
+    ("Home", Key::Home),
+    ("Insert", Key::Insert),
+    ("Menu", Key::Menu),
+    ("PageDown", Key::PageDown),
+    ("PageUp", Key::PageUp),
+    ("Pause", Key::Pause),
+    ("Space", Key::Space),
+    ("Tab", Key::Tab),
+    ("NumLock", Key::NumLock),
+    ("CapsLock", Key::CapsLock),
+    ("ScrollLock", Key::ScrollLock),
+    ("LeftShift", Key::LeftShift),
+    ("RightShift", Key::RightShift),
+    ("LeftCtrl", Key::LeftCtrl),
+    ("RightCtrl", Key::RightCtrl),
+    ("NumPad0", Key::NumPad0),
+    ("NumPad1", Key::NumPad1),
+    ("NumPad2", Key::NumPad2),
+    ("NumPad3", Key::NumPad3),
+    ("NumPad4", Key::NumPad4),
+    ("NumPad5", Key::NumPad5),
+    ("NumPad6", Key::NumPad6),
+    ("NumPad7", Key::NumPad7),
+    ("NumPad8", Key::NumPad8),
+    ("NumPad9", Key::NumPad9),
+    ("NumPadDot", Key::NumPadDot),
+    ("NumPadSlash", Key::NumPadSlash),
+    ("NumPadAsterisk", Key::NumPadAsterisk),
+    ("NumPadMinus", Key::NumPadMinus),
+    ("NumPadPlus", Key::NumPadPlus),
+    ("NumPadEnter", Key::NumPadEnter),
+    ("LeftAlt", Key::LeftAlt),
+    ("RightAlt", Key::RightAlt),
+    ("LeftSuper", Key::LeftSuper),
+    ("RightSuper", Key::RightSuper),
+];
+
+/// A key read from the config file by its name, like `"X"` or `"RightShift"`.
+/// The names are matched case-insensitively.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyBinding(pub Key);
+
+impl TryFrom<String> for KeyBinding {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        KEY_NAMES
+            .iter()
+            .find(|(key_name, _)| key_name.eq_ignore_ascii_case(&name))
+            .map(|&(_, key)| KeyBinding(key))
+            .ok_or_else(|| format!("unknown key `{name}`"))
+    }
+}
+
+impl std::fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match KEY_NAMES.iter().find(|(_, key)| *key == self.0) {
+            Some((name, _)) => write!(f, "{name}"),
+            None => write!(f, "{:?}", self.0),
+        }
+    }
+}
+
+/// The keys for each button of a controller.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ControllerBindings {
+    pub a: KeyBinding,
+    pub b: KeyBinding,
+    pub select: KeyBinding,
+    pub start: KeyBinding,
+    pub up: KeyBinding,
+    pub down: KeyBinding,
+    pub left: KeyBinding,
+    pub right: KeyBinding,
+}
+
+impl ControllerBindings {
+    fn player_one() -> Self {
+        Self {
+            a: KeyBinding(Key::X),
+            b: KeyBinding(Key::Z),
+            select: KeyBinding(Key::RightShift),
+            start: KeyBinding(Key::Enter),
+            up: KeyBinding(Key::Up),
+            down: KeyBinding(Key::Down),
+            left: KeyBinding(Key::Left),
+            right: KeyBinding(Key::Right),
+        }
+    }
+
+    fn player_two() -> Self {
+        Self {
+            a: KeyBinding(Key::H),
+            b: KeyBinding(Key::G),
+            select: KeyBinding(Key::T),
+            start: KeyBinding(Key::Y),
+            up: KeyBinding(Key::W),
+            down: KeyBinding(Key::S),
+            left: KeyBinding(Key::A),
+            right: KeyBinding(Key::D),
+        }
+    }
+
+    /// Returns each key with the button it is bound to.
+    pub fn buttons(&self) -> [(KeyBinding, u8); 8] {
+        [
+            (self.a, Buttons::A),
+            (self.b, Buttons::B),
+            (self.select, Buttons::SELECT),
+            (self.start, Buttons::START),
+            (self.up, Buttons::UP),
+            (self.down, Buttons::DOWN),
+            (self.left, Buttons::LEFT),
+            (self.right, Buttons::RIGHT),
+        ]
+    }
+
+    fn keys(&self) -> [(KeyBinding, &'static str); 8] {
+        [
+            (self.a, "a"),
+            (self.b, "b"),
+            (self.select, "select"),
+            (self.start, "start"),
+            (self.up, "up"),
+            (self.down, "down"),
+            (self.left, "left"),
+            (self.right, "right"),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hotkeys {
+    pub pause: KeyBinding,
+    pub reset: KeyBinding,
+    pub screenshot: KeyBinding,
+    /// Runs the emulator faster while it is held down.
+    pub fast_forward: KeyBinding,
+    pub toggle_orange: KeyBinding,
+    pub toggle_indigo: KeyBinding,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self {
+            pause: KeyBinding(Key::P),
+            reset: KeyBinding(Key::R),
+            screenshot: KeyBinding(Key::F12),
+            fast_forward: KeyBinding(Key::Tab),
+            toggle_orange: KeyBinding(Key::O),
+            toggle_indigo: KeyBinding(Key::I),
+        }
+    }
+}
+
+impl Hotkeys {
+    fn keys(&self) -> [(KeyBinding, &'static str); 6] {
+        [
+            (self.pause, "pause"),
+            (self.reset, "reset"),
+            (self.screenshot, "screenshot"),
+            (self.fast_forward, "fast_forward"),
+            (self.toggle_orange, "toggle_orange"),
+            (self.toggle_indigo, "toggle_indigo"),
+        ]
+    }
+}
+
+/// The user's settings, read from a TOML file like:
+///
+/// ```toml
+/// [player1]
+/// a = "X"
+/// b = "Z"
+/// select = "RightShift"
+/// start = "Enter"
+/// up = "Up"
+/// down = "Down"
+/// left = "Left"
+/// right = "Right"
+///
+/// [hotkeys]
+/// pause = "P"
+/// fast_forward = "Tab"
+/// ```
+///
+/// Every table is optional and falls back to the defaults, but a controller
+/// table has to bind all eight buttons.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub player1: ControllerBindings,
+    pub player2: ControllerBindings,
+    pub hotkeys: Hotkeys,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            player1: ControllerBindings::player_one(),
+            player2: ControllerBindings::player_two(),
+            hotkeys: Hotkeys::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Returns `$XDG_CONFIG_HOME/nes-emulator/config.toml`, or the platform's
+    /// equivalent.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|path| path.join("nes-emulator").join("config.toml"))
+    }
+
+    /// Loads the config file at `path`, or at [`Config::default_path()`] if no
+    /// path is given. A missing file is only an error if the path was given.
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let path = match (path, Self::default_path()) {
+            (Some(path), _) => path.to_path_buf(),
+            (None, Some(path)) if path.exists() => path,
+            (None, _) => return Ok(Self::default()),
+        };
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read the config file {}: {err}", path.display()))?;
+
+        Self::parse(&contents)
+            .map_err(|err| format!("Invalid config file {}: {err}", path.display()).into())
+    }
+
+    fn parse(contents: &str) -> Result<Self, Box<dyn Error>> {
+        let config: Self = toml::from_str(contents)?;
+        config.check_conflicts()?;
+
+        Ok(config)
+    }
+
+    /// Makes sure no key is bound to more than one thing. Binding a key to two
+    /// buttons would make it impossible to press just one of them.
+    fn check_conflicts(&self) -> Result<(), String> {
+        let tables = [
+            ("player1", self.player1.keys()),
+            ("player2", self.player2.keys()),
+        ];
+        let bindings = tables
+            .iter()
+            .flat_map(|(table, keys)| keys.map(|(key, name)| (key, format!("{table}.{name}"))))
+            .chain(
+                self.hotkeys
+                    .keys()
+                    .map(|(key, name)| (key, format!("hotkeys.{name}"))),
+            )
+            .collect::<Vec<_>>();
+
+        for (i, (key, name)) in bindings.iter().enumerate() {
+            if let Some((_, other_name)) = bindings[i + 1..].iter().find(|(other, _)| other == key)
+            {
+                return Err(format!(
+                    "the key `{key}` is bound to both `{name}` and `{other_name}`"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_tables_fall_back_to_the_defaults() {
+        let config = Config::parse(
+            r#"
+            [hotkeys]
+            pause = "space"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.player1, ControllerBindings::player_one());
+        assert_eq!(config.hotkeys.pause, KeyBinding(Key::Space));
+        assert_eq!(config.hotkeys.reset, Hotkeys::default().reset);
+    }
+
+    #[test]
+    fn the_default_bindings_do_not_conflict() {
+        assert!(Config::default().check_conflicts().is_ok());
+    }
+
+    #[test]
+    fn bad_bindings_are_reported() {
+        let unknown_key = Config::parse("[hotkeys]\nreset = \"Hyper\"").unwrap_err();
+        assert!(unknown_key.to_string().contains("unknown key `Hyper`"));
+
+        let conflict = Config::parse("[hotkeys]\nscreenshot = \"X\"").unwrap_err();
+        assert!(conflict
+            .to_string()
+            .contains("`X` is bound to both `player1.a` and `hotkeys.screenshot`"));
+
+        let unknown_button = Config::parse("[player2]\nturbo = \"Q\"").unwrap_err();
+        assert!(unknown_button.to_string().contains("unknown field `turbo`"));
+    }
+}
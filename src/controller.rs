@@ -0,0 +1,179 @@
+use crate::display::Pixels;
+use crate::zapper::Zapper;
+use clap::ValueEnum;
+use std::sync::Arc;
+
+/// The buttons of a standard controller, one bit each, in the order they are
+/// shifted out of the controller.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Buttons(pub u8);
+
+impl Buttons {
+    pub const A: u8 = 0b0000_0001;
+    pub const B: u8 = 0b0000_0010;
+    pub const SELECT: u8 = 0b0000_0100;
+    pub const START: u8 = 0b0000_1000;
+    pub const UP: u8 = 0b0001_0000;
+    pub const DOWN: u8 = 0b0010_0000;
+    pub const LEFT: u8 = 0b0100_0000;
+    pub const RIGHT: u8 = 0b1000_0000;
+
+    pub fn set(&mut self, button: u8, pressed: bool) {
+        match pressed {
+            true => self.0 |= button,
+            false => self.0 &= !button,
+        }
+    }
+}
+
+/// A standard controller. While the strobe is high, the shift register keeps
+/// being reloaded with the buttons, so reads return the state of A. Once it is
+/// low, every read shifts out the next button.
+#[derive(Default, Debug)]
+pub struct Controller {
+    buttons: Buttons,
+    shift_register: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+    }
+
+    fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+
+        if strobe {
+            self.shift_register = self.buttons.0;
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.shift_register = self.buttons.0;
+        }
+
+        let bit = self.shift_register & 1;
+        // Official controllers return 1 once all the buttons are shifted out.
+        self.shift_register = (self.shift_register >> 1) | 0x80;
+
+        bit
+    }
+}
+
+/// What is plugged into a controller port, selected from the command line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PortDevice {
+    #[default]
+    Controller,
+    Zapper,
+}
+
+enum Port {
+    Controller(Controller),
+    Zapper(Zapper),
+}
+
+/// The two controller ports at `$4016` and `$4017`.
+pub struct Controllers {
+    ports: [Port; 2],
+}
+
+impl Controllers {
+    /// Creates the ports with the given devices. Zappers look at `pixels` to
+    /// sense light.
+    pub fn with_devices(devices: [PortDevice; 2], pixels: &Arc<Pixels>) -> Self {
+        Self {
+            ports: devices.map(|device| match device {
+                PortDevice::Controller => Port::Controller(Controller::default()),
+                PortDevice::Zapper => Port::Zapper(Zapper::new(pixels.clone())),
+            }),
+        }
+    }
+
+    /// Sets the buttons of the controller in `port`, if it is a controller.
+    pub fn set_buttons(&mut self, port: usize, buttons: Buttons) {
+        if let Port::Controller(controller) = &mut self.ports[port] {
+            controller.set_buttons(buttons);
+        }
+    }
+
+    /// Sets the trigger and aim of every Zapper.
+    pub fn set_zapper_input(&mut self, trigger: bool, aim: Option<(usize, usize)>) {
+        for port in &mut self.ports {
+            if let Port::Zapper(zapper) = port {
+                zapper.set_input(trigger, aim);
+            }
+        }
+    }
+
+    /// Handles a write to `$4016`. Bit 0 is the strobe of both ports.
+    pub fn write_strobe(&mut self, byte: u8) {
+        for port in &mut self.ports {
+            if let Port::Controller(controller) = port {
+                controller.write_strobe(byte & 1 != 0);
+            }
+        }
+    }
+
+    /// Handles a read of `$4016` (port 0) or `$4017` (port 1) while the PPU is
+    /// at `scanline` and `dot`. Only the lower bits are driven by the device, the
+    /// upper bits are open bus, which is the high byte of the address for the
+    /// usual `LDA $4016`.
+    pub fn read(&mut self, port: usize, open_bus: u8, scanline: usize, dot: usize) -> u8 {
+        let byte = match &mut self.ports[port] {
+            Port::Controller(controller) => controller.read(),
+            Port::Zapper(zapper) => zapper.read(scanline, dot),
+        };
+
+        (open_bus & 0xE0) | byte
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controllers() -> Controllers {
+        Controllers::with_devices([PortDevice::Controller; 2], &Arc::new(Pixels::new()))
+    }
+
+    fn read_all(controllers: &mut Controllers, port: usize) -> Vec<u8> {
+        (0..10)
+            .map(|_| controllers.read(port, 0x40, 0, 0))
+            .collect()
+    }
+
+    #[test]
+    fn buttons_are_shifted_out_in_order_after_the_strobe() {
+        let mut controllers = controllers();
+        controllers.set_buttons(0, Buttons(Buttons::A | Buttons::START | Buttons::RIGHT));
+        controllers.set_buttons(1, Buttons(Buttons::B));
+
+        controllers.write_strobe(1);
+        controllers.write_strobe(0);
+
+        assert_eq!(
+            read_all(&mut controllers, 0),
+            [0x41, 0x40, 0x40, 0x41, 0x40, 0x40, 0x40, 0x41, 0x41, 0x41]
+        );
+        assert_eq!(
+            read_all(&mut controllers, 1),
+            [0x40, 0x41, 0x40, 0x40, 0x40, 0x40, 0x40, 0x40, 0x41, 0x41]
+        );
+    }
+
+    #[test]
+    fn reads_return_a_while_the_strobe_is_high() {
+        let mut controllers = controllers();
+        controllers.set_buttons(0, Buttons(Buttons::A));
+        controllers.write_strobe(1);
+
+        assert_eq!(controllers.read(0, 0x40, 0, 0), 0x41);
+        assert_eq!(controllers.read(0, 0x40, 0, 0), 0x41);
+
+        controllers.set_buttons(0, Buttons::default());
+        assert_eq!(controllers.read(0, 0x40, 0, 0), 0x40);
+    }
+}
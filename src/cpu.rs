@@ -1,4 +1,9 @@
-use crate::{apu::Apu, cartridge::Cartridge, ppu::Ppu};
+use crate::{
+    apu::{Apu, APU_STATUS},
+    cartridge::{open_bus, Cartridge},
+    controller::Controllers,
+    ppu::Ppu,
+};
 use nes6502::{Cpu, Interrupts, Mapper};
 use std::{cell::RefCell, rc::Rc};
 
@@ -11,6 +16,15 @@ const PPUSCROLL: u16 = 0x2005;
 const PPUADDR: u16 = 0x2006;
 const PPUDATA: u16 = 0x2007;
 const OAMDMA: u16 = 0x4014;
+const JOY1: u16 = 0x4016;
+const JOY2: u16 = 0x4017;
+
+/// An OAM DMA halts the CPU for one cycle, then alternates 256 reads and writes.
+/// One more alignment cycle is needed if the DMA starts on an odd CPU cycle.
+const OAM_DMA_CYCLES: u16 = 513;
+/// Each DMC sample fetch halts the CPU for up to 4 cycles. The exact amount
+/// depends on what the CPU was doing, so the worst case is always charged.
+const DMC_DMA_CYCLES: u16 = 4;
 
 /// We use a container that holds both interrupt states. Each interrupt state is stored in an
 /// `Rc<Refcell<bool>>` internally so that we can use [`InterruptsContainer::share()`] to create a new
@@ -98,9 +112,12 @@ impl CpuContainer {
         &mut self,
         ppu: Rc<RefCell<Ppu>>,
         apu: Rc<RefCell<Apu>>,
+        controllers: Rc<RefCell<Controllers>>,
         cartridge: Rc<RefCell<Cartridge>>,
     ) {
-        self.0.memory_mapper.initialize(ppu, apu, cartridge);
+        self.0
+            .memory_mapper
+            .initialize(ppu, apu, controllers, cartridge);
         self.0.initialize();
     }
 
@@ -108,6 +125,11 @@ impl CpuContainer {
         self.0.initialized() && self.0.memory_mapper.initialized()
     }
 
+    /// Works like the reset button, the CPU starts over from the reset vector.
+    pub fn reset(&mut self) {
+        self.0.initialize();
+    }
+
     /// Runs a full instruction cycle. Returns the amount of
     /// cpu cycles taken.
     pub fn cycle(&mut self) -> u8 {
@@ -135,14 +157,37 @@ impl CpuContainer {
 
         cycles
     }
+
+    /// Reads a DMC sample byte for the APU through the CPU bus. The CPU is
+    /// charged for the fetch through [`CpuContainer::take_stall_cycles()`].
+    pub fn read_dmc_sample(&mut self, address: u16) -> u8 {
+        self.0.memory_mapper.dmc_stall_cycles += DMC_DMA_CYCLES;
+        self.0.memory_mapper.read(address)
+    }
+
+    /// Returns the amount of cycles the CPU is stalled for by DMC fetches and an
+    /// OAM DMA started since the last call, where `total_cpu_cycles` is the cycle
+    /// count after the last instruction. Returns 0 if no DMA happened.
+    pub fn take_stall_cycles(&mut self, total_cpu_cycles: u64) -> u16 {
+        let dmc_stall_cycles = std::mem::take(&mut self.0.memory_mapper.dmc_stall_cycles);
+
+        if !std::mem::take(&mut self.0.memory_mapper.oam_dma_requested) {
+            return dmc_stall_cycles;
+        }
+
+        dmc_stall_cycles + OAM_DMA_CYCLES + (total_cpu_cycles % 2) as u16
+    }
 }
 
 pub struct CpuMemoryMapper {
     ram: [u8; 0x2000],
     ppu: Option<Rc<RefCell<Ppu>>>,
     apu: Option<Rc<RefCell<Apu>>>,
+    controllers: Option<Rc<RefCell<Controllers>>>,
     cartridge: Option<Rc<RefCell<Cartridge>>>,
     initialized: bool,
+    oam_dma_requested: bool,
+    dmc_stall_cycles: u16,
 }
 
 impl CpuMemoryMapper {
@@ -151,8 +196,11 @@ impl CpuMemoryMapper {
             ram: [0; 0x2000],
             ppu: None,
             apu: None,
+            controllers: None,
             cartridge: None,
             initialized: false,
+            oam_dma_requested: false,
+            dmc_stall_cycles: 0,
         }
     }
 
@@ -160,10 +208,12 @@ impl CpuMemoryMapper {
         &mut self,
         ppu: Rc<RefCell<Ppu>>,
         apu: Rc<RefCell<Apu>>,
+        controllers: Rc<RefCell<Controllers>>,
         cartridge: Rc<RefCell<Cartridge>>,
     ) {
         self.ppu = Some(ppu);
         self.apu = Some(apu);
+        self.controllers = Some(controllers);
         self.cartridge = Some(cartridge);
 
         self.initialized = true;
@@ -172,6 +222,20 @@ impl CpuMemoryMapper {
     fn initialized(&self) -> bool {
         self.initialized
     }
+
+    /// Copies the 256 bytes of page `$XX00-$XXFF` into OAM through OAMDATA. The
+    /// copy happens all at once, and the CPU is charged for it afterwards through
+    /// [`CpuContainer::take_stall_cycles()`].
+    fn oam_dma(&mut self, page: u8) {
+        let start = (page as u16) << 8;
+
+        for offset in 0..=0xFF {
+            let byte = self.read(start + offset);
+            self.ppu.as_ref().unwrap().borrow_mut().write_oam_data(byte);
+        }
+
+        self.oam_dma_requested = true;
+    }
 }
 
 impl Mapper for CpuMemoryMapper {
@@ -184,13 +248,29 @@ impl Mapper for CpuMemoryMapper {
                 let adjusted_address = 0x2000 + ((address - 0x2000) % 8);
 
                 match adjusted_address {
-                    PPUSTATUS => self.ppu.as_ref().unwrap().borrow().read_ppu_status(),
+                    PPUSTATUS => self.ppu.as_ref().unwrap().borrow_mut().read_ppu_status(),
                     OAMDATA => self.ppu.as_ref().unwrap().borrow().read_oam_data(),
+                    PPUDATA => self.ppu.as_ref().unwrap().borrow_mut().read_ppu_data(),
                     _ => panic!("Illegal PPU Operation"),
                 }
             }
-            // Saved for APU
-            0x4000..=0x4017 => unimplemented!(),
+            // Handle the APU and I/O registers.
+            0x4000..=0x4017 => match address {
+                APU_STATUS => self.apu.as_ref().unwrap().borrow_mut().read_status(),
+                JOY1 | JOY2 => {
+                    // The Zapper needs to know where the beam is to sense light.
+                    let (scanline, dot) = self.ppu.as_ref().unwrap().borrow().beam_position();
+
+                    self.controllers.as_ref().unwrap().borrow_mut().read(
+                        (address - JOY1) as usize,
+                        open_bus(address),
+                        scanline,
+                        dot,
+                    )
+                }
+                // Nothing else here can be read.
+                _ => open_bus(address),
+            },
             // Disabled
             0x4018..=0x401F => unimplemented!(),
             // Route to cartridge mapper
@@ -208,20 +288,41 @@ impl Mapper for CpuMemoryMapper {
 
                 match adjusted_address {
                     PPUCTRL => self.ppu.as_ref().unwrap().borrow_mut().write_ppu_ctrl(byte),
-                    PPUMASK => self.ppu.as_ref().unwrap().borrow_mut().write_ppu_mask(),
-                    OAMADDR => self.ppu.as_ref().unwrap().borrow_mut().write_oam_addr(),
-                    OAMDATA => self.ppu.as_ref().unwrap().borrow_mut().write_oam_data(),
-                    PPUSCROLL => self.ppu.as_ref().unwrap().borrow_mut().write_ppu_scroll(),
-                    PPUADDR => self.ppu.as_ref().unwrap().borrow_mut().write_ppu_addr(),
-                    PPUDATA => self.ppu.as_ref().unwrap().borrow_mut().write_ppu_data(),
+                    PPUMASK => self.ppu.as_ref().unwrap().borrow_mut().write_ppu_mask(byte),
+                    OAMADDR => self.ppu.as_ref().unwrap().borrow_mut().write_oam_addr(byte),
+                    OAMDATA => self.ppu.as_ref().unwrap().borrow_mut().write_oam_data(byte),
+                    PPUSCROLL => self
+                        .ppu
+                        .as_ref()
+                        .unwrap()
+                        .borrow_mut()
+                        .write_ppu_scroll(byte),
+                    PPUADDR => self.ppu.as_ref().unwrap().borrow_mut().write_ppu_addr(byte),
+                    PPUDATA => self.ppu.as_ref().unwrap().borrow_mut().write_ppu_data(byte),
                     _ => panic!("Illegal PPU Operation"),
                 }
+
+                if let Some(cartridge) = &self.cartridge {
+                    cartridge
+                        .borrow_mut()
+                        .ppu_register_write(adjusted_address, byte);
+                }
             }
-            // Saved for APU
+            // Handle the APU and I/O registers.
             0x4000..=0x4017 => match address {
-                OAMDMA => {
-                    unimplemented!()
-                }
+                OAMDMA => self.oam_dma(byte),
+                JOY1 => self
+                    .controllers
+                    .as_ref()
+                    .unwrap()
+                    .borrow_mut()
+                    .write_strobe(byte),
+                0x4000..=0x4013 | APU_STATUS | 0x4017 => self
+                    .apu
+                    .as_ref()
+                    .unwrap()
+                    .borrow_mut()
+                    .write_register(address, byte),
                 _ => {
                     // do nothing for now
                 }
@@ -238,3 +339,67 @@ impl Mapper for CpuMemoryMapper {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::PortDevice;
+    use crate::display::Pixels;
+    use crate::ines::Ines;
+    use std::sync::Arc;
+
+    fn memory_mapper() -> CpuMemoryMapper {
+        let mut memory_mapper = CpuMemoryMapper::new();
+        memory_mapper.initialize(
+            Rc::new(RefCell::new(Ppu::new())),
+            Rc::new(RefCell::new(Apu::new())),
+            Rc::new(RefCell::new(Controllers::with_devices(
+                [PortDevice::Controller; 2],
+                &Arc::new(Pixels::new()),
+            ))),
+            Rc::new(RefCell::new(Cartridge::new(Ines::default()))),
+        );
+
+        memory_mapper
+    }
+
+    #[test]
+    fn write_only_apu_registers_read_as_open_bus() {
+        let memory_mapper = memory_mapper();
+
+        assert_eq!(memory_mapper.read(0x4000), 0x40);
+        assert_eq!(memory_mapper.read(OAMDMA), 0x40);
+    }
+
+    #[test]
+    fn oam_dma_copies_page_starting_at_oam_addr() {
+        let mut memory_mapper = memory_mapper();
+        for offset in 0..0x100 {
+            memory_mapper.write(0x0200 + offset, offset as u8);
+        }
+
+        memory_mapper.write(OAMADDR, 0x04);
+        memory_mapper.write(OAMDMA, 0x02);
+
+        let ppu = memory_mapper.ppu.clone().unwrap();
+        ppu.borrow_mut().write_oam_addr(0x04);
+        assert_eq!(ppu.borrow().read_oam_data(), 0x00);
+        ppu.borrow_mut().write_oam_addr(0x03);
+        assert_eq!(ppu.borrow().read_oam_data(), 0xFF);
+        assert!(memory_mapper.oam_dma_requested);
+    }
+
+    #[test]
+    fn oam_dma_stalls_for_an_extra_cycle_on_odd_cycles() {
+        let mut cpu = CpuContainer::new();
+
+        assert_eq!(cpu.take_stall_cycles(100), 0);
+
+        cpu.0.memory_mapper.oam_dma_requested = true;
+        assert_eq!(cpu.take_stall_cycles(100), 513);
+        assert_eq!(cpu.take_stall_cycles(100), 0);
+
+        cpu.0.memory_mapper.oam_dma_requested = true;
+        assert_eq!(cpu.take_stall_cycles(101), 514);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::ppu;
 use rgb::Rgb;
+use std::path::Path;
 use std::sync::Mutex;
 
 pub const WIDTH: usize = ppu::VISIBLE_DOTS;
@@ -18,9 +19,9 @@ impl Pixels {
         let i = (y * WIDTH) + x;
         let raw: u32 = self.0.lock().unwrap()[i];
 
-        let r = ((raw >> 16) & 0xF) as u8;
-        let g = ((raw >> 8) & 0xF) as u8;
-        let b = (raw & 0xF) as u8;
+        let r = ((raw >> 16) & 0xFF) as u8;
+        let g = ((raw >> 8) & 0xFF) as u8;
+        let b = (raw & 0xFF) as u8;
 
         Rgb { r, g, b }
     }
@@ -30,6 +31,15 @@ impl Pixels {
         self.0.lock().unwrap()[i] = ((rgb.r as u32) << 16) | ((rgb.g as u32) << 8) | (rgb.b as u32);
     }
 
+    pub fn save_png(&self, path: &Path) -> image::ImageResult<()> {
+        let image = image::RgbImage::from_fn(WIDTH as u32, HEIGHT as u32, |x, y| {
+            let rgb = self.read(x as usize, y as usize);
+            image::Rgb([rgb.r, rgb.g, rgb.b])
+        });
+
+        image.save(path)
+    }
+
     pub fn copy_to_buffer(&self, buffer: &mut [u32]) {
         buffer.copy_from_slice(self.0.lock().unwrap().as_slice())
     }
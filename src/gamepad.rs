@@ -0,0 +1,300 @@
+use crate::controller::Buttons;
+use crossbeam_channel::{Receiver, Sender};
+use evdev::{AbsoluteAxisType, AttributeSetRef, Device, Key};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+/// How far a stick has to be pushed from the centre to press a direction, as a
+/// fraction of its range.
+const STICK_THRESHOLD: f32 = 0.5;
+/// How often to look for newly connected gamepads.
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The gamepad buttons for each NES button. The face buttons are mapped by
+/// position, so B and A sit side by side like on the NES controller.
+const BUTTONS: [(Key, u8); 8] = [
+    (Key::BTN_EAST, Buttons::A),
+    (Key::BTN_SOUTH, Buttons::B),
+    (Key::BTN_SELECT, Buttons::SELECT),
+    (Key::BTN_START, Buttons::START),
+    (Key::BTN_DPAD_UP, Buttons::UP),
+    (Key::BTN_DPAD_DOWN, Buttons::DOWN),
+    (Key::BTN_DPAD_LEFT, Buttons::LEFT),
+    (Key::BTN_DPAD_RIGHT, Buttons::RIGHT),
+];
+
+/// The axes that work as a D-pad, as (horizontal, vertical). Most gamepads
+/// report their D-pad as the first hat rather than as buttons.
+const DIRECTION_AXES: [(AbsoluteAxisType, AbsoluteAxisType); 2] = [
+    (AbsoluteAxisType::ABS_HAT0X, AbsoluteAxisType::ABS_HAT0Y),
+    (AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y),
+];
+
+/// Reads the gamepads connected through evdev. Gamepads are picked up when they
+/// are plugged in and dropped when they are unplugged. Each one takes the
+/// first port that doesn't have a gamepad yet.
+///
+/// Looking for gamepads opens every input device, which is too slow for the
+/// render thread, so a thread of its own does it and hands new gamepads over.
+///
+/// Virtual uinput devices show up like any other gamepad, so this can be driven
+/// without real hardware.
+pub struct Gamepads {
+    gamepads: Vec<Gamepad>,
+    /// The gamepads found by the scanning thread.
+    found: Receiver<(PathBuf, Device)>,
+    /// The paths of the gamepads in use, which the scanning thread skips.
+    connected: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+struct Gamepad {
+    path: PathBuf,
+    device: Device,
+    port: usize,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        let (tx, found) = crossbeam_channel::unbounded();
+        let connected = Arc::new(Mutex::new(Vec::new()));
+
+        let scanned_connected = connected.clone();
+        spawn(move || scan(&tx, &scanned_connected));
+
+        Self {
+            gamepads: Vec::new(),
+            found,
+            connected,
+        }
+    }
+
+    /// Presses the buttons held on each gamepad in `controller_buttons`, on top
+    /// of the ones already pressed on the keyboard.
+    pub fn read_into(&mut self, controller_buttons: &mut [Buttons; 2]) {
+        while let Ok((path, device)) = self.found.try_recv() {
+            self.connect(path, device);
+        }
+
+        let gamepads = self.gamepads.len();
+        self.gamepads.retain(|gamepad| match gamepad.read() {
+            Ok(buttons) => {
+                controller_buttons[gamepad.port].0 |= buttons.0;
+                true
+            }
+            Err(_) => {
+                println!("Disconnected the gamepad for player {}", gamepad.port + 1);
+                false
+            }
+        });
+
+        if self.gamepads.len() != gamepads {
+            self.update_connected();
+        }
+    }
+
+    /// Gives the gamepad the first free port. It is dropped when both ports are
+    /// taken, and found again on a later scan.
+    fn connect(&mut self, path: PathBuf, device: Device) {
+        if self.gamepads.iter().any(|gamepad| gamepad.path == path) {
+            return;
+        }
+
+        let Some(port) =
+            (0..2).find(|port| self.gamepads.iter().all(|gamepad| gamepad.port != *port))
+        else {
+            return;
+        };
+
+        println!(
+            "Connected {} as the gamepad for player {}",
+            device.name().unwrap_or("a gamepad"),
+            port + 1
+        );
+        self.gamepads.push(Gamepad { path, device, port });
+        self.update_connected();
+    }
+
+    fn update_connected(&self) {
+        *self.connected.lock().unwrap() = self
+            .gamepads
+            .iter()
+            .map(|gamepad| gamepad.path.clone())
+            .collect();
+    }
+}
+
+/// Sends every gamepad that isn't connected yet, once per `SCAN_INTERVAL`, until
+/// `Gamepads` is dropped.
+fn scan(tx: &Sender<(PathBuf, Device)>, connected: &Arc<Mutex<Vec<PathBuf>>>) {
+    // `Gamepads` holds the only other reference.
+    while Arc::strong_count(connected) > 1 {
+        let skipped = connected.lock().unwrap().clone();
+
+        // Both ports are taken, so there is no need to open every input device.
+        if skipped.len() < 2 {
+            for (path, device) in evdev::enumerate() {
+                if !is_gamepad(&device) || skipped.contains(&path) {
+                    continue;
+                }
+
+                if tx.send((path, device)).is_err() {
+                    return;
+                }
+            }
+        }
+
+        sleep(SCAN_INTERVAL);
+    }
+}
+
+impl Gamepad {
+    /// Fails once the gamepad is unplugged.
+    fn read(&self) -> std::io::Result<Buttons> {
+        let keys = self.device.get_key_state()?;
+        let axes = self.device.get_abs_state()?;
+        let supported_axes = self.device.supported_absolute_axes();
+
+        Ok(map_state(&keys, |axis| {
+            match supported_axes.is_some_and(|supported| supported.contains(axis)) {
+                true => {
+                    let info = axes[axis.0 as usize];
+                    axis_position(info.value, info.minimum, info.maximum)
+                }
+                false => 0.0,
+            }
+        }))
+    }
+}
+
+fn is_gamepad(device: &Device) -> bool {
+    device
+        .supported_keys()
+        .is_some_and(|keys| keys.contains(Key::BTN_SOUTH))
+}
+
+/// Scales an axis value to -1.0 to 1.0.
+fn axis_position(value: i32, minimum: i32, maximum: i32) -> f32 {
+    if maximum <= minimum {
+        return 0.0;
+    }
+
+    let centre = (minimum as f32 + maximum as f32) / 2.0;
+    (value as f32 - centre) / ((maximum - minimum) as f32 / 2.0)
+}
+
+/// Turns the held gamepad buttons and the position of each axis into NES buttons.
+fn map_state(
+    keys: &AttributeSetRef<Key>,
+    axis_position: impl Fn(AbsoluteAxisType) -> f32,
+) -> Buttons {
+    let mut buttons = Buttons::default();
+
+    for (key, button) in BUTTONS {
+        if keys.contains(key) {
+            buttons.set(button, true);
+        }
+    }
+
+    for (horizontal, vertical) in DIRECTION_AXES {
+        let x = axis_position(horizontal);
+        let y = axis_position(vertical);
+
+        if x <= -STICK_THRESHOLD {
+            buttons.set(Buttons::LEFT, true);
+        }
+        if x >= STICK_THRESHOLD {
+            buttons.set(Buttons::RIGHT, true);
+        }
+        if y <= -STICK_THRESHOLD {
+            buttons.set(Buttons::UP, true);
+        }
+        if y >= STICK_THRESHOLD {
+            buttons.set(Buttons::DOWN, true);
+        }
+    }
+
+    buttons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::uinput::VirtualDeviceBuilder;
+    use evdev::{AbsInfo, AttributeSet, EventType, InputEvent, UinputAbsSetup};
+
+    #[test]
+    fn buttons_and_axes_map_to_nes_buttons() {
+        let mut keys = AttributeSet::new();
+        keys.insert(Key::BTN_EAST);
+        keys.insert(Key::BTN_START);
+
+        let buttons = map_state(&keys, |axis| match axis {
+            AbsoluteAxisType::ABS_HAT0X => -1.0,
+            // A stick that is only pushed a little doesn't count.
+            AbsoluteAxisType::ABS_Y => 0.3,
+            _ => 0.0,
+        });
+
+        assert_eq!(
+            buttons,
+            Buttons(Buttons::A | Buttons::START | Buttons::LEFT)
+        );
+    }
+
+    #[test]
+    fn axis_positions_are_centred() {
+        assert_eq!(axis_position(0, 0, 255), -1.0);
+        assert_eq!(axis_position(-32768, -32768, 32767), -1.0);
+        assert_eq!(axis_position(1, -1, 1), 1.0);
+        assert_eq!(axis_position(5, 0, 0), 0.0);
+    }
+
+    #[test]
+    #[ignore = "needs write access to /dev/uinput"]
+    fn virtual_gamepads_are_picked_up_and_read() {
+        let mut keys = AttributeSet::new();
+        for (key, _) in BUTTONS {
+            keys.insert(key);
+        }
+        let hat = UinputAbsSetup::new(AbsoluteAxisType::ABS_HAT0Y, AbsInfo::new(0, -1, 1, 0, 0, 0));
+
+        let mut virtual_device = VirtualDeviceBuilder::new()
+            .unwrap()
+            .name("Virtual NES gamepad")
+            .with_keys(&keys)
+            .unwrap()
+            .with_absolute_axis(&hat)
+            .unwrap()
+            .build()
+            .unwrap();
+        let path = virtual_device
+            .enumerate_dev_nodes_blocking()
+            .unwrap()
+            .flatten()
+            .find(|path| path.to_string_lossy().contains("event"))
+            .unwrap();
+
+        virtual_device
+            .emit(&[
+                InputEvent::new(EventType::KEY, Key::BTN_SOUTH.code(), 1),
+                InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_HAT0Y.0, 1),
+            ])
+            .unwrap();
+
+        // The scanning thread picks it up on its first scan.
+        let mut gamepads = Gamepads::new();
+        while gamepads.gamepads.iter().all(|gamepad| gamepad.path != path) {
+            gamepads.read_into(&mut [Buttons::default(); 2]);
+            sleep(Duration::from_millis(10));
+        }
+        let gamepad = gamepads
+            .gamepads
+            .iter()
+            .find(|gamepad| gamepad.path == path)
+            .unwrap();
+
+        assert_eq!(gamepad.read().unwrap(), Buttons(Buttons::B | Buttons::DOWN));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::region::Region;
+
 const HEADER_BYTES: usize = 16;
 const KB: usize = 1024;
 
@@ -24,17 +26,34 @@ impl Ines {
 
         let program_rom_size_multiplier = header_bytes[4];
         let character_rom_size_multiplier = header_bytes[5];
-        let mapper_number = header_bytes[7] >> 4;
-        let nametable_arrangement = match header_bytes[6] & 0x0000_0001 != 0 {
+        let mapper_number = (header_bytes[7] & 0xF0) | (header_bytes[6] >> 4);
+        let nametable_arrangement = match header_bytes[6] & 0b0000_0001 != 0 {
             true => NametableArrangement::HorizontalArrangement,
             false => NametableArrangement::VerticalArrangement,
         };
+        let alternative_nametable_layout = header_bytes[6] & 0b0000_1000 != 0;
+        let is_nes2 = header_bytes[7] & 0b0000_1100 == 0b0000_1000;
+        let region = match header_bytes[12] & 0b0000_0011 {
+            _ if !is_nes2 => None,
+            0 => Some(Region::Ntsc),
+            1 => Some(Region::Pal),
+            // Multi-region games run on any console, so they are left to the default.
+            2 => None,
+            _ => Some(Region::Dendy),
+        };
+        let submapper_number = match is_nes2 {
+            true => header_bytes[8] >> 4,
+            false => 0,
+        };
 
         let header = Header {
             program_rom_size_multiplier,
             character_rom_size_multiplier,
             nametable_arrangement,
+            alternative_nametable_layout,
+            region,
             mapper_number,
+            submapper_number,
         };
 
         let program_rom_size = program_rom_size_multiplier as usize * KB * PROGRAM_BLOCK_SIZE;
@@ -77,8 +96,14 @@ pub struct Header {
     // Size of CHR ROM in 8 KB units (value 0 means the board uses CHR RAM)
     pub character_rom_size_multiplier: u8,
     pub nametable_arrangement: NametableArrangement,
-    // bits flags_7[8:=5] set as the lowest nibble
+    // Set when the board uses its own nametable layout, which is usually four-screen VRAM
+    pub alternative_nametable_layout: bool,
+    // The CPU/PPU timing from byte 12 of an NES 2.0 header, if the rom has one
+    pub region: Option<Region>,
+    // The upper nibble from flags 7 and the lower nibble from flags 6
     pub mapper_number: u8,
+    // The board variant from byte 8 of an NES 2.0 header, or 0 when unknown
+    pub submapper_number: u8,
 }
 
 impl Default for Header {
@@ -89,7 +114,10 @@ impl Default for Header {
             program_rom_size_multiplier: DEFAULT_PROGRAM_ROM_SIZE_MULTIPLIER,
             character_rom_size_multiplier: DEFAULT_CHARACTER_ROM_SIZE_MULTIPLIER,
             nametable_arrangement: NametableArrangement::default(),
+            alternative_nametable_layout: false,
+            region: None,
             mapper_number: 0,
+            submapper_number: 0,
         }
     }
 }
@@ -1,22 +1,37 @@
 use apu::Apu;
+use audio::{AudioOptions, AudioOutput, DEFAULT_SAMPLE_RATE};
 use cartridge::Cartridge;
 use clap::Parser;
+use config::Config;
+use controller::{Controllers, PortDevice};
 use cpu::CpuContainer;
 use debug::Tile;
+use display::Pixels;
 use ines::Ines;
-use ppu::Ppu;
+use ppu::{MasterPalette, PaletteVariant, Ppu};
+use region::Region;
+use runtime::{Pacing, RuntimeOptions};
 use std::cell::RefCell;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::Arc;
 
 mod apu;
+mod audio;
 mod cartridge;
+mod config;
+mod controller;
 mod cpu;
 mod debug;
 mod display;
+#[cfg(feature = "gamepad")]
+mod gamepad;
 mod graphical_debug;
 mod ines;
 mod ppu;
+mod region;
 mod runtime;
+mod zapper;
 
 pub struct MapperType {}
 
@@ -28,6 +43,39 @@ struct Args {
     /// Prints the CHR-ROM pattern table to the terminal.
     #[clap(short, long, default_value = None)]
     pattern_table: bool,
+    /// The built-in master palette used to turn PPU color indexes into RGB.
+    #[clap(long, value_enum, default_value_t = PaletteVariant::default())]
+    palette: PaletteVariant,
+    /// A 192 or 1536 byte `.pal` file to use instead of a built-in palette.
+    #[clap(long, default_value = None)]
+    palette_file: Option<String>,
+    /// The console region to emulate. Defaults to the region in the rom's NES 2.0
+    /// header, or NTSC if it doesn't have one.
+    #[clap(long, value_enum, default_value = None)]
+    region: Option<Region>,
+    /// What is plugged into the first controller port.
+    #[clap(long, value_enum, default_value_t = PortDevice::default())]
+    port1: PortDevice,
+    /// What is plugged into the second controller port. The Zapper is aimed
+    /// with the mouse and fired with the left mouse button.
+    #[clap(long, value_enum, default_value_t = PortDevice::default())]
+    port2: PortDevice,
+    /// Where to send the audio.
+    #[clap(long, value_enum, default_value_t = AudioOutput::default())]
+    audio: AudioOutput,
+    /// The file to record to with `--audio wav`.
+    #[clap(long, default_value = "audio.wav")]
+    audio_file: PathBuf,
+    /// The sample rate to record at with `--audio wav`. Audio devices use their own rate.
+    #[clap(long, default_value_t = DEFAULT_SAMPLE_RATE)]
+    sample_rate: u32,
+    /// The config file with the key bindings. Defaults to
+    /// `$XDG_CONFIG_HOME/nes-emulator/config.toml` if it exists.
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Whether to pace the emulator off the display or off the audio device.
+    #[clap(long, value_enum, default_value_t = Pacing::default())]
+    pacing: Pacing,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -38,19 +86,59 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
-    runtime::run(move || initialize_emulator(rom))?;
+    let master_palette = match &args.palette_file {
+        Some(path) => MasterPalette::from_pal_file(&std::fs::read(path)?)?,
+        None => MasterPalette::builtin(args.palette),
+    };
+
+    let config = Config::load(args.config.as_deref())?;
+    let region = args.region.or(rom.header.region).unwrap_or_default();
+    let options = RuntimeOptions {
+        region,
+        config,
+        audio: AudioOptions {
+            output: args.audio,
+            wav_path: args.audio_file,
+            sample_rate: args.sample_rate,
+        },
+        pacing: args.pacing,
+    };
+
+    let port_devices = [args.port1, args.port2];
+
+    runtime::run(options, move |pixels| {
+        initialize_emulator(rom, master_palette, region, port_devices, pixels)
+    })?;
     Ok(())
 }
 
-fn initialize_emulator(rom: Ines) -> runtime::Emulator {
+fn initialize_emulator(
+    rom: Ines,
+    master_palette: MasterPalette,
+    region: Region,
+    port_devices: [PortDevice; 2],
+    pixels: &Arc<Pixels>,
+) -> runtime::Emulator {
     let cpu = Rc::new(RefCell::new(CpuContainer::new()));
     let ppu = Rc::new(RefCell::new(Ppu::new()));
+    ppu.borrow_mut().set_master_palette(master_palette);
+    ppu.borrow_mut().set_region(region);
+
     let apu = Rc::new(RefCell::new(Apu::new()));
+    let controllers = Rc::new(RefCell::new(Controllers::with_devices(
+        port_devices,
+        pixels,
+    )));
     let cartridge = Rc::new(RefCell::new(Cartridge::new(rom)));
 
-    cpu.borrow_mut()
-        .initialize(ppu.clone(), apu.clone(), cartridge.clone());
-    ppu.borrow_mut().initialize(cpu.clone());
+    cpu.borrow_mut().initialize(
+        ppu.clone(),
+        apu.clone(),
+        controllers.clone(),
+        cartridge.clone(),
+    );
+    ppu.borrow_mut().initialize(cpu.clone(), cartridge.clone());
+    apu.borrow_mut().set_region(region);
     apu.borrow_mut().initialize();
     cartridge.borrow_mut().initialize(cpu.clone(), ppu.clone());
 
@@ -59,7 +147,7 @@ fn initialize_emulator(rom: Ines) -> runtime::Emulator {
     assert!(apu.borrow().initialized());
     assert!(cartridge.borrow().initialized());
 
-    runtime::Emulator::from_initialized(cpu, ppu, cartridge)
+    runtime::Emulator::from_initialized(cpu, ppu, apu, controllers, cartridge)
 }
 
 fn check_and_run_debug(args: &Args, rom: &Ines) -> bool {
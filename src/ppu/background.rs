@@ -0,0 +1,177 @@
+use super::Ppu;
+
+const NAMETABLE_START_ADDRESS: u16 = 0x2000;
+const ATTRIBUTE_TABLE_OFFSET: u16 = 0x03C0;
+
+/// The latches filled by the four memory fetches of every 8-dot tile period, and the
+/// shift registers they are reloaded into. The upper 8 bits of each shift register
+/// hold the tile currently being drawn, and the lower 8 bits hold the next tile.
+#[derive(Default)]
+pub(super) struct BackgroundPipeline {
+    nametable_latch: u8,
+    attribute_latch: u8,
+    pattern_low_latch: u8,
+    pattern_high_latch: u8,
+    pattern_low_shifter: u16,
+    pattern_high_shifter: u16,
+    attribute_low_shifter: u16,
+    attribute_high_shifter: u16,
+}
+
+impl BackgroundPipeline {
+    fn reload(&mut self) {
+        self.pattern_low_shifter =
+            (self.pattern_low_shifter & 0xFF00) | self.pattern_low_latch as u16;
+        self.pattern_high_shifter =
+            (self.pattern_high_shifter & 0xFF00) | self.pattern_high_latch as u16;
+
+        // The attribute bits stay the same for all 8 pixels of a tile, so they are
+        // expanded to a full byte to shift alongside the pattern bits.
+        let expand = |bit: u8| match self.attribute_latch & bit != 0 {
+            true => 0x00FF,
+            false => 0x0000,
+        };
+        self.attribute_low_shifter = (self.attribute_low_shifter & 0xFF00) | expand(0b01);
+        self.attribute_high_shifter = (self.attribute_high_shifter & 0xFF00) | expand(0b10);
+    }
+
+    fn shift(&mut self) {
+        self.pattern_low_shifter <<= 1;
+        self.pattern_high_shifter <<= 1;
+        self.attribute_low_shifter <<= 1;
+        self.attribute_high_shifter <<= 1;
+    }
+
+    /// Returns the palette number (0-3) and the pixel value (0-3) at the output
+    /// of the shift registers, which fine X scroll selects from the current tile.
+    pub(super) fn output(&self, fine_x_scroll: u8) -> (u8, u8) {
+        let mask = 0x8000 >> fine_x_scroll;
+        let bit = |shifter: u16| u8::from(shifter & mask != 0);
+
+        let pixel = bit(self.pattern_low_shifter) | (bit(self.pattern_high_shifter) << 1);
+        let palette = bit(self.attribute_low_shifter) | (bit(self.attribute_high_shifter) << 1);
+
+        (palette, pixel)
+    }
+}
+
+impl Ppu {
+    /// Runs the background fetches and shifts for the current dot. This is only
+    /// called on the visible and pre-render scanlines.
+    pub(super) fn clock_background(&mut self) {
+        if matches!(self.dot, 2..=257 | 322..=337) {
+            self.background.shift();
+        }
+
+        if matches!(self.dot, 9..=257 | 329..=337) && self.dot % 8 == 1 {
+            self.background.reload();
+        }
+
+        if matches!(self.dot, 1..=256 | 321..=336) {
+            match (self.dot - 1) % 8 {
+                0 => self.fetch_nametable_byte(),
+                2 => self.fetch_attribute_byte(),
+                4 => self.fetch_pattern_low_byte(),
+                6 => self.fetch_pattern_high_byte(),
+                7 => self.increment_coarse_x(),
+                _ => {}
+            }
+        }
+
+        // Two unused nametable fetches end the scanline. Mappers like the MMC5
+        // count scanlines by them.
+        if matches!(self.dot, 337 | 339) {
+            self.fetch_nametable_byte();
+        }
+
+        match self.dot {
+            256 => self.increment_y(),
+            257 => self.copy_horizontal_scroll(),
+            280..=304 if self.scanline == self.region.pre_render_scanline() => {
+                self.copy_vertical_scroll()
+            }
+            _ => {}
+        }
+    }
+
+    fn fetch_nametable_byte(&mut self) {
+        let address = NAMETABLE_START_ADDRESS | (self.vram_address & 0x0FFF);
+        self.background.nametable_latch = self.read_vram(address);
+    }
+
+    fn fetch_attribute_byte(&mut self) {
+        let v = self.vram_address;
+        let address = NAMETABLE_START_ADDRESS
+            | ATTRIBUTE_TABLE_OFFSET
+            | (v & 0x0C00)
+            | ((v >> 4) & 0x38)
+            | ((v >> 2) & 0x07);
+
+        // Each attribute byte covers a 4x4 tile area, split into 2x2 tile quadrants
+        // that are picked by bit 1 of the coarse X and coarse Y scroll.
+        let shift = ((v >> 4) & 0b100) | (v & 0b10);
+        self.background.attribute_latch = (self.read_vram(address) >> shift) & 0b11;
+    }
+
+    fn fetch_pattern_low_byte(&mut self) {
+        let address = self.background_pattern_address();
+        self.background.pattern_low_latch = self.read_vram(address);
+    }
+
+    fn fetch_pattern_high_byte(&mut self) {
+        let address = self.background_pattern_address() + 8;
+        self.background.pattern_high_latch = self.read_vram(address);
+    }
+
+    fn background_pattern_address(&self) -> u16 {
+        let fine_y = (self.vram_address >> 12) & 0b111;
+
+        ((self.ppu_status.background_pattern_table_address() as u16) << 12)
+            + ((self.background.nametable_latch as u16) << 4)
+            + fine_y
+    }
+
+    /// Moves `v` to the next tile, wrapping into the horizontally adjacent nametable.
+    pub(super) fn increment_coarse_x(&mut self) {
+        match self.vram_address & 0x001F {
+            31 => {
+                self.vram_address &= !0x001F;
+                self.vram_address ^= 0x0400;
+            }
+            _ => self.vram_address += 1,
+        }
+    }
+
+    /// Moves `v` to the next pixel row. Coarse Y wraps into the vertically adjacent
+    /// nametable after row 29, but rows 30 and 31 (the attribute table) wrap around
+    /// within the same nametable.
+    pub(super) fn increment_y(&mut self) {
+        if self.vram_address & 0x7000 != 0x7000 {
+            self.vram_address += 0x1000;
+            return;
+        }
+
+        self.vram_address &= !0x7000;
+
+        let coarse_y = match (self.vram_address & 0x03E0) >> 5 {
+            29 => {
+                self.vram_address ^= 0x0800;
+                0
+            }
+            31 => 0,
+            coarse_y => coarse_y + 1,
+        };
+
+        self.vram_address = (self.vram_address & !0x03E0) | (coarse_y << 5);
+    }
+
+    /// v: ....A.. ...BCDEF <- t: ....A.. ...BCDEF
+    fn copy_horizontal_scroll(&mut self) {
+        self.vram_address = (self.vram_address & !0x041F) | (self.temporary_vram_address & 0x041F);
+    }
+
+    /// v: GHIA.BC DEF..... <- t: GHIA.BC DEF.....
+    fn copy_vertical_scroll(&mut self) {
+        self.vram_address = (self.vram_address & !0x7BE0) | (self.temporary_vram_address & 0x7BE0);
+    }
+}
@@ -1,33 +1,35 @@
+use crate::cartridge::{Cartridge, Mirroring};
 use crate::cpu::CpuContainer;
 use crate::display::Pixels;
+use crate::region::Region;
+use background::BackgroundPipeline;
 use nes6502::Interrupts;
+use palette::{palette_ram_index, PALETTE_RAM_SIZE};
 use rgb::Rgb;
+use sprites::{SpritePipeline, OAM_SIZE};
 use std::{cell::RefCell, rc::Rc};
 
+pub use palette::{MasterPalette, PaletteVariant};
+
+mod background;
+mod palette;
+mod sprites;
+
 pub const VISIBLE_DOTS: usize = 256;
 pub const VISIBLE_SCANLINES: usize = 240;
 pub const DOTS_PER_SCANLINE: usize = 341;
-pub const POST_RENDER_SCANLINES: usize = 1;
-pub const VBLANK_SCANLINES: usize = 20;
 pub const PRE_RENDER_SCANLINES: usize = 1;
-pub const TOTAL_SCANLINES: usize =
-    VISIBLE_SCANLINES + POST_RENDER_SCANLINES + VBLANK_SCANLINES + PRE_RENDER_SCANLINES;
-pub const PPU_DOTS_PER_CPU_CYCLE: usize = 3;
-pub const PPU_DOTS_PER_FRAME: usize = DOTS_PER_SCANLINE * TOTAL_SCANLINES;
-pub const CPU_CYCLES_PER_FRAME: f64 = PPU_DOTS_PER_FRAME as f64 / PPU_DOTS_PER_CPU_CYCLE as f64;
-
-const VBLANK_START_SCANLINE: usize = VISIBLE_SCANLINES + POST_RENDER_SCANLINES;
-const PRE_RENDER_SCANLINE: usize = TOTAL_SCANLINES - 1;
-const PPUSTATUS_VBLANK: u8 = 0b1000_0000;
 
-/// Uses the [RP2C04-0004](https://www.nesdev.org/wiki/PPU_palettes#RP2C04-0004) palette.
-#[allow(clippy::zero_prefixed_literal)]
-const PALETTE: [u16; 64] = [
-    430, 326, 044, 660, 000, 755, 014, 630, 555, 310, 070, 003, 764, 770, 040, 572, 737, 200, 027,
-    747, 000, 222, 510, 740, 653, 053, 447, 140, 403, 000, 473, 357, 503, 031, 420, 006, 407, 507,
-    333, 704, 022, 666, 036, 020, 111, 773, 444, 707, 757, 777, 320, 700, 760, 276, 777, 467, 000,
-    750, 637, 567, 360, 657, 077, 120,
-];
+const PPUSTATUS_VBLANK: u8 = 0b1000_0000;
+const PPUSTATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+const PPUSTATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+const CIRAM_SIZE: usize = 0x0800;
+const FOUR_SCREEN_NAMETABLES_SIZE: usize = 0x1000;
+const VRAM_ADDRESS_MASK: u16 = 0x3FFF;
+const PALETTE_START_ADDRESS: u16 = 0x3F00;
+/// How many dots the CPU has to notice an NMI after it is raised. Reading
+/// PPUSTATUS within this window clears vblank in time to suppress the NMI.
+const NMI_DELAY_DOTS: u8 = 1;
 
 /// Holds the status of the ppu for PPUCTRL
 pub struct PpuStatus(u8);
@@ -86,6 +88,55 @@ impl PpuStatus {
     }
 }
 
+/// Holds the rendering settings of PPUMASK
+pub struct PpuMask(u8);
+
+impl PpuMask {
+    pub fn new() -> Self {
+        Self(0)
+    }
+
+    /// Greyscale (0: normal color, 1: produce a greyscale display)
+    pub fn greyscale(&self) -> bool {
+        self.0 & 0b0000_0001 != 0
+    }
+
+    /// Show background in leftmost 8 pixels of screen (0: Hide, 1: Show)
+    pub fn show_background_left(&self) -> bool {
+        self.0 & 0b0000_0010 != 0
+    }
+
+    /// Show sprites in leftmost 8 pixels of screen (0: Hide, 1: Show)
+    pub fn show_sprites_left(&self) -> bool {
+        self.0 & 0b0000_0100 != 0
+    }
+
+    /// Show background (0: Hide, 1: Show)
+    pub fn show_background(&self) -> bool {
+        self.0 & 0b0000_1000 != 0
+    }
+
+    /// Show sprites (0: Hide, 1: Show)
+    pub fn show_sprites(&self) -> bool {
+        self.0 & 0b0001_0000 != 0
+    }
+
+    /// Returns the red, green and blue emphasis bits as a code from 0-7,
+    /// with red in bit 0, green in bit 1 and blue in bit 2.
+    pub fn color_emphasis(&self) -> u8 {
+        self.0 >> 5
+    }
+
+    /// The PPU only fetches, evaluates sprites and moves `v` while rendering is enabled.
+    pub fn rendering_enabled(&self) -> bool {
+        self.show_background() || self.show_sprites()
+    }
+
+    pub fn set(&mut self, byte: u8) {
+        self.0 = byte;
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct PpuDebugSnapshot {
     pub scanline: usize,
@@ -98,12 +149,49 @@ pub struct PpuDebugSnapshot {
 pub struct Ppu {
     pub registers: [u8; 8],
     pub ppu_status: PpuStatus,
+    pub ppu_mask: PpuMask,
     pub cpu: Option<Rc<RefCell<CpuContainer>>>,
+    pub cartridge: Option<Rc<RefCell<Cartridge>>>,
     pub initialized: bool,
+    /// Decides the amount of scanlines per frame and when vblank starts.
+    region: Region,
     scanline: usize,
     dot: usize,
     frame: u64,
     in_vblank: bool,
+    /// The level of the NMI output, which is high while both the vblank flag
+    /// and the PPUCTRL NMI enable bit are set. The CPU triggers on its rising edge.
+    nmi_output: bool,
+    /// Counts down the dots until a raised NMI reaches the CPU.
+    nmi_delay: Option<u8>,
+    /// Set when PPUSTATUS is read one dot before vblank starts, which keeps the
+    /// vblank flag from being set for that frame.
+    suppress_vblank: bool,
+    /// The 2 KB of CIRAM in the console, extended by the 2 KB of VRAM on
+    /// the cartridge when four-screen mirroring is used.
+    nametables: Vec<u8>,
+    mirroring: Mirroring,
+    /// The current VRAM address (`v`). Only the lower 15 bits are used.
+    vram_address: u16,
+    /// The temporary VRAM address (`t`), which holds the address being assembled
+    /// by PPUADDR/PPUSCROLL writes. Only the lower 15 bits are used.
+    temporary_vram_address: u16,
+    /// The fine X scroll (`x`). Only the lower 3 bits are used.
+    fine_x_scroll: u8,
+    /// The shared first/second write toggle (`w`) of PPUSCROLL and PPUADDR.
+    write_latch: bool,
+    /// PPUDATA reads below the palettes are delayed by one read through this buffer.
+    read_buffer: u8,
+    /// The last address the PPU fetched from, which the cartridge gets to see
+    /// every dot while rendering.
+    bus_address: u16,
+    background: BackgroundPipeline,
+    /// Primary OAM, holding 64 sprites of 4 bytes each.
+    oam: [u8; OAM_SIZE],
+    oam_address: u8,
+    sprites: SpritePipeline,
+    palette_ram: [u8; PALETTE_RAM_SIZE],
+    master_palette: MasterPalette,
 }
 
 impl Ppu {
@@ -114,18 +202,43 @@ impl Ppu {
         Self {
             registers: [0; 8],
             ppu_status: PpuStatus::new(),
+            ppu_mask: PpuMask::new(),
             cpu: None,
+            cartridge: None,
             initialized: false,
+            region: Region::default(),
             scanline: 0,
             dot: 0,
             frame: 0,
             in_vblank: false,
+            nmi_output: false,
+            nmi_delay: None,
+            suppress_vblank: false,
+            nametables: vec![0; CIRAM_SIZE],
+            mirroring: Mirroring::default(),
+            vram_address: 0,
+            temporary_vram_address: 0,
+            fine_x_scroll: 0,
+            write_latch: false,
+            read_buffer: 0,
+            bus_address: 0,
+            background: BackgroundPipeline::default(),
+            oam: [0; OAM_SIZE],
+            oam_address: 0,
+            sprites: SpritePipeline::default(),
+            palette_ram: [0; PALETTE_RAM_SIZE],
+            master_palette: MasterPalette::default(),
         }
     }
 
     /// Initialize the PPU.
-    pub fn initialize(&mut self, cpu: Rc<RefCell<CpuContainer>>) {
+    pub fn initialize(
+        &mut self,
+        cpu: Rc<RefCell<CpuContainer>>,
+        cartridge: Rc<RefCell<Cartridge>>,
+    ) {
         self.cpu = Some(cpu);
+        self.cartridge = Some(cartridge);
         self.initialized = true;
     }
 
@@ -143,16 +256,89 @@ impl Ppu {
         }
     }
 
+    /// Returns the scanline and dot the PPU is at.
+    pub fn beam_position(&self) -> (usize, usize) {
+        (self.scanline, self.dot)
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// Sets the palette used to turn color indexes from palette RAM into RGB.
+    pub fn set_master_palette(&mut self, master_palette: MasterPalette) {
+        self.master_palette = master_palette;
+    }
+
+    /// Sets how nametable addresses are mapped onto nametable memory. The
+    /// cartridge calls this on startup, and again whenever its mapper switches
+    /// the mirroring.
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        let size = match mirroring {
+            Mirroring::FourScreen => FOUR_SCREEN_NAMETABLES_SIZE,
+            _ => CIRAM_SIZE,
+        };
+
+        self.nametables.resize(size, 0);
+        self.mirroring = mirroring;
+    }
+
+    /// Works like the reset button, which clears PPUCTRL, PPUMASK and the
+    /// PPUSCROLL/PPUADDR write latch, turning off rendering and NMIs.
+    pub fn reset(&mut self) {
+        self.write_ppu_ctrl(0);
+        self.write_ppu_mask(0);
+        self.write_latch = false;
+    }
+
     pub fn clock(&mut self, pixels: &Pixels) {
-        if self.scanline < VISIBLE_SCANLINES && self.dot < VISIBLE_DOTS {
-            pixels.write(self.dot, self.scanline, self.pixel_color());
+        self.clock_nmi();
+
+        if self.dot == 1 {
+            if self.scanline == self.region.vblank_start_scanline() {
+                self.start_vblank();
+            } else if self.scanline == self.region.pre_render_scanline() {
+                self.end_vblank();
+            }
+        }
+
+        let rendering = self.ppu_mask.rendering_enabled()
+            && (self.scanline < VISIBLE_SCANLINES
+                || self.scanline == self.region.pre_render_scanline());
+
+        if rendering {
+            self.clock_background();
+            self.clock_sprites();
+        }
+
+        // Dot 0 is idle, so pixel x is output on dot x + 1.
+        if self.scanline < VISIBLE_SCANLINES && (1..=VISIBLE_DOTS).contains(&self.dot) {
+            let color = self.pixel_color();
+            pixels.write(self.dot - 1, self.scanline, color);
+        }
+
+        // Outside of rendering, `v` is left on the bus, so PPUADDR writes and
+        // PPUDATA accesses toggle the address lines as well.
+        if !rendering {
+            self.bus_address = self.vram_address & VRAM_ADDRESS_MASK;
+        }
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.borrow_mut().ppu_bus(self.bus_address);
         }
 
         self.advance_dot();
     }
 
     fn advance_dot(&mut self) {
-        self.dot += 1;
+        // On odd frames the last dot of the pre-render scanline is skipped
+        // while rendering is enabled, which makes those frames one dot shorter.
+        let skip_dot = self.region.skips_odd_frame_dot()
+            && self.scanline == self.region.pre_render_scanline()
+            && self.dot == DOTS_PER_SCANLINE - 2
+            && self.frame % 2 == 1
+            && self.ppu_mask.rendering_enabled();
+
+        self.dot += 1 + usize::from(skip_dot);
 
         if self.dot < DOTS_PER_SCANLINE {
             return;
@@ -161,77 +347,229 @@ impl Ppu {
         self.dot = 0;
         self.scanline += 1;
 
-        if self.scanline == VBLANK_START_SCANLINE {
-            self.start_vblank();
+        if self.scanline == self.region.total_scanlines() {
+            self.scanline = 0;
+            self.frame = self.frame.wrapping_add(1);
         }
+    }
 
-        if self.scanline == PRE_RENDER_SCANLINE {
-            self.end_vblank();
+    fn pixel_color(&mut self) -> Rgb<u8> {
+        let x = self.dot - 1;
+
+        let (background_palette, background_pixel) = match self.ppu_mask.show_background()
+            && (x >= 8 || self.ppu_mask.show_background_left())
+        {
+            true => self.background.output(self.fine_x_scroll),
+            false => (0, 0),
+        };
+
+        let sprite = self.sprites.output(x).filter(|_| {
+            self.ppu_mask.show_sprites() && (x >= 8 || self.ppu_mask.show_sprites_left())
+        });
+
+        if let Some(sprite) = sprite {
+            if sprite.is_sprite_zero && background_pixel != 0 {
+                self.detect_sprite_zero_hit(x);
+            }
         }
 
-        if self.scanline == TOTAL_SCANLINES {
-            self.scanline = 0;
-            self.frame = self.frame.wrapping_add(1);
+        let (palette, pixel) = match (background_pixel, sprite) {
+            (_, None) => (background_palette, background_pixel),
+            (0, Some(sprite)) => (sprite.palette, sprite.pixel),
+            (_, Some(sprite)) if !sprite.behind_background => (sprite.palette, sprite.pixel),
+            (_, Some(_)) => (background_palette, background_pixel),
+        };
+
+        let palette_address = match pixel {
+            // While rendering is disabled and `v` points into the palettes, the
+            // backdrop is drawn with the color `v` points at instead of $3F00.
+            _ if !self.ppu_mask.rendering_enabled()
+                && self.vram_address & VRAM_ADDRESS_MASK >= PALETTE_START_ADDRESS =>
+            {
+                self.vram_address
+            }
+            // Transparent pixels show the universal backdrop color at $3F00.
+            0 => PALETTE_START_ADDRESS,
+            _ => PALETTE_START_ADDRESS + (palette as u16 * 4) + pixel as u16,
+        };
+
+        let mut color_index = self.read_vram(palette_address);
+
+        if self.ppu_mask.greyscale() {
+            color_index &= 0x30;
         }
-    }
 
-    fn pixel_color(&self) -> Rgb<u8> {
-        let stripe = ((self.scanline / 8) + (self.frame as usize % 4)) % 4;
+        self.master_palette
+            .color(color_index, self.ppu_mask.color_emphasis())
+    }
 
-        match stripe {
-            0 => Rgb {
-                r: 0x24,
-                g: 0x5C,
-                b: 0xA6,
-            },
-            1 => Rgb {
-                r: 0x1F,
-                g: 0x8F,
-                b: 0x4C,
-            },
-            2 => Rgb {
-                r: 0xB7,
-                g: 0x87,
-                b: 0x2C,
-            },
-            _ => Rgb {
-                r: 0x8A,
-                g: 0x44,
-                b: 0xAD,
-            },
+    /// Called when an opaque sprite 0 pixel overlaps an opaque background pixel.
+    /// Pixels clipped from the left 8 pixels are already transparent by now, so
+    /// the only exception left is that a hit is never detected at x=255.
+    fn detect_sprite_zero_hit(&mut self, x: usize) {
+        if x == 255 {
+            return;
         }
+
+        self.registers[2] |= PPUSTATUS_SPRITE_ZERO_HIT;
     }
 
     fn start_vblank(&mut self) {
         self.in_vblank = true;
-        self.registers[2] |= PPUSTATUS_VBLANK;
-
-        if self.ppu_status.generate_nmi_on_blanking() == 1 {
-            if let Some(cpu) = &self.cpu {
-                cpu.borrow_mut()
-                    .0
-                    .interrupts
-                    .set_non_maskable_interrupt_state(true);
-            }
+
+        if !std::mem::take(&mut self.suppress_vblank) {
+            self.registers[2] |= PPUSTATUS_VBLANK;
         }
+
+        self.update_nmi_output();
     }
 
     fn end_vblank(&mut self) {
         self.in_vblank = false;
-        self.registers[2] &= !PPUSTATUS_VBLANK;
+        self.registers[2] &=
+            !(PPUSTATUS_VBLANK | PPUSTATUS_SPRITE_ZERO_HIT | PPUSTATUS_SPRITE_OVERFLOW);
+        self.update_nmi_output();
+    }
+
+    /// Recomputes the NMI output after the vblank flag or the PPUCTRL NMI enable
+    /// bit changed. A rising edge raises an NMI, which includes enabling NMIs in
+    /// PPUCTRL while the vblank flag is still set. If the output drops again before
+    /// the CPU noticed, the NMI is lost.
+    fn update_nmi_output(&mut self) {
+        let nmi_output = self.registers[2] & PPUSTATUS_VBLANK != 0
+            && self.ppu_status.generate_nmi_on_blanking() == 1;
+
+        match (self.nmi_output, nmi_output) {
+            (false, true) => self.nmi_delay = Some(NMI_DELAY_DOTS),
+            (true, false) => self.nmi_delay = None,
+            _ => {}
+        }
+
+        self.nmi_output = nmi_output;
+    }
+
+    /// Passes a raised NMI on to the CPU once its delay has run out. This happens
+    /// on the PPU clock rather than when the NMI is raised, as register writes
+    /// can raise an NMI while the CPU is still busy with the instruction.
+    fn clock_nmi(&mut self) {
+        self.nmi_delay = match self.nmi_delay {
+            Some(0) => {
+                if let Some(cpu) = &self.cpu {
+                    cpu.borrow_mut()
+                        .0
+                        .interrupts
+                        .set_non_maskable_interrupt_state(true);
+                }
+
+                None
+            }
+            delay => delay.map(|dots| dots - 1),
+        };
+    }
+
+    /// Reads from the PPU address space, where $3000-$3EFF mirrors the nametables.
+    /// The pattern tables live on the cartridge, which also gets the chance to
+    /// take nametable reads away from CIRAM.
+    fn read_vram(&mut self, address: u16) -> u8 {
+        let address = address & VRAM_ADDRESS_MASK;
+        // Palette RAM is inside the PPU, so reading it doesn't reach the bus.
+        if address < PALETTE_START_ADDRESS {
+            self.bus_address = address;
+        }
+
+        let cartridge = self.cartridge.as_ref().unwrap();
+
+        match address {
+            0x0000..=0x1FFF => cartridge.borrow_mut().ppu_read(address),
+            0x2000..=0x3EFF => {
+                let nametable_address = 0x2000 | (address & 0x0FFF);
+
+                match cartridge.borrow_mut().read_nametable(nametable_address) {
+                    Some(byte) => byte,
+                    None => self.nametables[self.mirroring.nametable_index(address)],
+                }
+            }
+            _ => self.palette_ram[palette_ram_index(address)],
+        }
+    }
+
+    fn write_vram(&mut self, address: u16, byte: u8) {
+        let address = address & VRAM_ADDRESS_MASK;
+        let cartridge = self.cartridge.as_ref().unwrap();
+
+        match address {
+            0x0000..=0x1FFF => cartridge.borrow_mut().ppu_write(address, byte),
+            0x2000..=0x3EFF => {
+                let nametable_address = 0x2000 | (address & 0x0FFF);
+
+                if !cartridge
+                    .borrow_mut()
+                    .write_nametable(nametable_address, byte)
+                {
+                    self.nametables[self.mirroring.nametable_index(address)] = byte;
+                }
+            }
+            // Palette RAM entries are only 6 bits wide.
+            _ => self.palette_ram[palette_ram_index(address)] = byte & 0x3F,
+        }
+    }
+
+    /// Moves `v` along after a PPUDATA access, either across (1) or down (32).
+    fn increment_vram_address(&mut self) {
+        let increment = match self.ppu_status.vram_address_increment() {
+            0 => 1,
+            _ => 32,
+        };
+
+        self.vram_address = (self.vram_address + increment) & 0x7FFF;
     }
 }
 
 // "Read" Ppu controls
 impl Ppu {
-    pub fn read_ppu_status(&self) -> u8 {
-        self.registers[2]
+    /// Reading PPUSTATUS clears the vblank flag and resets the PPUSCROLL/PPUADDR write latch.
+    ///
+    /// Reading it on the dot before vblank starts returns the flag as clear and
+    /// keeps it from being set for the frame, while reading it just after vblank
+    /// started clears the flag before the NMI reaches the CPU.
+    pub fn read_ppu_status(&mut self) -> u8 {
+        if self.scanline == self.region.vblank_start_scanline() && self.dot == 1 {
+            self.suppress_vblank = true;
+        }
+
+        let status = self.registers[2];
+
+        self.registers[2] &= !PPUSTATUS_VBLANK;
+        self.write_latch = false;
+        self.update_nmi_output();
+
+        status
+    }
+
+    /// Reads the byte at `v`. Reads below the palettes return the contents of the
+    /// internal read buffer, which is then refilled from `v`. Palette reads return
+    /// immediately, but still refill the buffer with the nametable byte "underneath".
+    pub fn read_ppu_data(&mut self) -> u8 {
+        let address = self.vram_address & VRAM_ADDRESS_MASK;
+
+        let byte = match address >= PALETTE_START_ADDRESS {
+            true => {
+                self.read_buffer = self.read_vram(address - 0x1000);
+                self.read_vram(address)
+            }
+            false => {
+                let buffered = self.read_buffer;
+                self.read_buffer = self.read_vram(address);
+                buffered
+            }
+        };
+
+        self.increment_vram_address();
+        byte
     }
 
     pub fn read_oam_data(&self) -> u8 {
-        dbg!("read oam data");
-        // Unimplemented
-        0
+        self.oam[self.oam_address as usize]
     }
 }
 
@@ -240,30 +578,75 @@ impl Ppu {
     pub fn write_ppu_ctrl(&mut self, byte: u8) {
         self.registers[0] = byte;
         self.ppu_status.set(byte);
+        self.update_nmi_output();
+        // t: ...GH.. ........ <- d: ......GH
+        self.temporary_vram_address =
+            (self.temporary_vram_address & !0x0C00) | ((byte as u16 & 0b11) << 10);
     }
 
-    pub fn write_ppu_mask(&mut self) {
-        // Unimplemented
+    pub fn write_ppu_mask(&mut self, byte: u8) {
+        self.registers[1] = byte;
+        self.ppu_mask.set(byte);
     }
 
-    pub fn write_oam_addr(&mut self) {
-        // Unimplemented
+    pub fn write_oam_addr(&mut self, byte: u8) {
+        self.registers[3] = byte;
+        self.oam_address = byte;
     }
 
-    pub fn write_oam_data(&mut self) {
-        // Unimplemented
+    pub fn write_oam_data(&mut self, byte: u8) {
+        self.registers[4] = byte;
+        self.oam[self.oam_address as usize] = byte;
+        self.oam_address = self.oam_address.wrapping_add(1);
     }
 
-    pub fn write_ppu_scroll(&mut self) {
-        // Unimplemented
+    pub fn write_ppu_scroll(&mut self, byte: u8) {
+        self.registers[5] = byte;
+
+        match self.write_latch {
+            false => {
+                // t: ....... ...ABCDE <- d: ABCDE...
+                // x:              FGH <- d: .....FGH
+                self.temporary_vram_address =
+                    (self.temporary_vram_address & !0x001F) | (byte as u16 >> 3);
+                self.fine_x_scroll = byte & 0b111;
+            }
+            true => {
+                // t: FGH..AB CDE..... <- d: ABCDEFGH
+                self.temporary_vram_address = (self.temporary_vram_address & !0x73E0)
+                    | ((byte as u16 & 0b111) << 12)
+                    | ((byte as u16 >> 3) << 5);
+            }
+        }
+
+        self.write_latch = !self.write_latch;
     }
 
-    pub fn write_ppu_addr(&mut self) {
-        // Unimplemented
+    pub fn write_ppu_addr(&mut self, byte: u8) {
+        self.registers[6] = byte;
+
+        match self.write_latch {
+            false => {
+                // t: .CDEFGH ........ <- d: ..CDEFGH
+                // Bit 14 of t is cleared as well.
+                self.temporary_vram_address =
+                    (self.temporary_vram_address & 0x00FF) | ((byte as u16 & 0x3F) << 8);
+            }
+            true => {
+                // t: ....... ABCDEFGH <- d: ABCDEFGH
+                // v: <...all bits...> <- t: <...all bits...>
+                self.temporary_vram_address = (self.temporary_vram_address & 0xFF00) | byte as u16;
+                self.vram_address = self.temporary_vram_address;
+            }
+        }
+
+        self.write_latch = !self.write_latch;
     }
 
-    pub fn write_ppu_data(&mut self) {
-        // Unimplemented
+    pub fn write_ppu_data(&mut self, byte: u8) {
+        self.registers[7] = byte;
+        self.write_vram(self.vram_address, byte);
+        self.increment_vram_address();
     }
 }
 
@@ -271,43 +654,574 @@ impl Ppu {
 mod tests {
     use super::*;
     use crate::display::Pixels;
+    use crate::ines::{Header, Ines};
+
+    const PATTERN_TABLES_SIZE: usize = 0x2000;
+    const VBLANK_START_SCANLINE: usize = Region::Ntsc.vblank_start_scanline();
+    const PRE_RENDER_SCANLINE: usize = Region::Ntsc.pre_render_scanline();
+    const PPU_DOTS_PER_FRAME: usize = DOTS_PER_SCANLINE * Region::Ntsc.total_scanlines();
+
+    /// Creates a PPU attached to an NROM cartridge holding `character_rom`.
+    fn ppu_with_character_rom(character_rom: Vec<u8>) -> Ppu {
+        let ines = Ines {
+            character_rom,
+            ..Ines::default()
+        };
+
+        let mut ppu = Ppu::new();
+        ppu.cartridge = Some(Rc::new(RefCell::new(Cartridge::new(ines))));
+        ppu
+    }
+
+    fn test_ppu() -> Ppu {
+        ppu_with_character_rom(vec![0; PATTERN_TABLES_SIZE])
+    }
 
     #[test]
     fn clock_writes_visible_pixels_by_dot_and_scanline() {
         let pixels = Pixels::new();
-        let mut ppu = Ppu::new();
+        let mut ppu = test_ppu();
+        ppu.write_vram(0x3F00, 0x21);
 
+        ppu.clock(&pixels);
         ppu.clock(&pixels);
 
-        assert_ne!(pixels.read(0, 0), Rgb { r: 0, g: 0, b: 0 });
-        assert_eq!(ppu.debug_snapshot().dot, 1);
+        assert_eq!(pixels.read(0, 0), palette_color(0x21));
+        assert_eq!(ppu.debug_snapshot().dot, 2);
         assert_eq!(ppu.debug_snapshot().scanline, 0);
     }
 
     #[test]
-    fn vblank_starts_after_visible_and_post_render_scanlines() {
+    fn background_tiles_render_through_palette_ram() {
         let pixels = Pixels::new();
-        let mut ppu = Ppu::new();
 
-        for _ in 0..DOTS_PER_SCANLINE * VBLANK_START_SCANLINE {
+        // Tile 1 has a single pixel of value 1 in its top-left corner, and tile 2
+        // has its top-left pixel set to value 3.
+        let mut character_rom = vec![0; PATTERN_TABLES_SIZE];
+        character_rom[0x10] = 0b1000_0000;
+        character_rom[0x20] = 0b1000_0000;
+        character_rom[0x28] = 0b1000_0000;
+        let mut ppu = ppu_with_character_rom(character_rom);
+        ppu.write_ppu_mask(0b0001_1110);
+
+        ppu.write_vram(0x2000, 0x01);
+        ppu.write_vram(0x2001, 0x02);
+        ppu.write_vram(0x3F00, 0x0F);
+        ppu.write_vram(0x3F01, 0x16);
+        ppu.write_vram(0x3F03, 0x2A);
+
+        ppu.scanline = PRE_RENDER_SCANLINE;
+        clock_until(&mut ppu, &pixels, 0, 17);
+
+        assert_eq!(pixels.read(0, 0), palette_color(0x16));
+        assert_eq!(pixels.read(1, 0), palette_color(0x0F));
+        assert_eq!(pixels.read(8, 0), palette_color(0x2A));
+    }
+
+    #[test]
+    fn pattern_table_writes_only_reach_character_ram() {
+        let mut ppu = test_ppu();
+        ppu.write_vram(0x0010, 0xAB);
+        assert_eq!(ppu.read_vram(0x0010), 0x00);
+
+        let ines = Ines {
+            header: Header {
+                character_rom_size_multiplier: 0,
+                ..Header::default()
+            },
+            character_rom: Vec::new(),
+            ..Ines::default()
+        };
+        ppu.cartridge = Some(Rc::new(RefCell::new(Cartridge::new(ines))));
+
+        ppu.write_vram(0x0010, 0xAB);
+        assert_eq!(ppu.read_vram(0x0010), 0xAB);
+    }
+
+    #[test]
+    fn vblank_starts_on_dot_1_after_visible_and_post_render_scanlines() {
+        let pixels = Pixels::new();
+        let mut ppu = test_ppu();
+
+        for _ in 0..DOTS_PER_SCANLINE * VBLANK_START_SCANLINE + 1 {
             ppu.clock(&pixels);
         }
 
         let snapshot = ppu.debug_snapshot();
         assert_eq!(snapshot.scanline, VBLANK_START_SCANLINE);
-        assert_eq!(snapshot.dot, 0);
-        assert!(snapshot.in_vblank);
+        assert_eq!(snapshot.dot, 1);
+        assert!(!snapshot.in_vblank);
+
+        ppu.clock(&pixels);
+        assert!(ppu.debug_snapshot().in_vblank);
         assert_ne!(ppu.read_ppu_status() & PPUSTATUS_VBLANK, 0);
     }
 
+    #[test]
+    fn odd_frames_skip_a_dot_while_rendering() {
+        let pixels = Pixels::new();
+        let mut ppu = test_ppu();
+        ppu.write_ppu_mask(0b0000_1000);
+
+        let mut frame_lengths = Vec::new();
+
+        for _ in 0..3 {
+            let mut dots = 0;
+            let frame = ppu.frame;
+
+            while ppu.frame == frame {
+                ppu.clock(&pixels);
+                dots += 1;
+            }
+
+            frame_lengths.push(dots);
+        }
+
+        assert_eq!(
+            frame_lengths,
+            [
+                PPU_DOTS_PER_FRAME,
+                PPU_DOTS_PER_FRAME - 1,
+                PPU_DOTS_PER_FRAME
+            ]
+        );
+
+        // Without rendering, every frame is the same length.
+        ppu.write_ppu_mask(0);
+        clock_until(
+            &mut ppu,
+            &pixels,
+            PRE_RENDER_SCANLINE,
+            DOTS_PER_SCANLINE - 1,
+        );
+        assert_eq!(ppu.frame % 2, 1);
+    }
+
+    #[test]
+    fn reading_ppu_status_near_vblank_suppresses_the_flag_or_nmi() {
+        let pixels = Pixels::new();
+        let mut ppu = test_ppu();
+        ppu.write_ppu_ctrl(0b1000_0000);
+
+        // One dot early: the flag reads as clear and is never set this frame.
+        clock_until(&mut ppu, &pixels, VBLANK_START_SCANLINE, 1);
+        assert_eq!(ppu.read_ppu_status() & PPUSTATUS_VBLANK, 0);
+        ppu.clock(&pixels);
+        assert_eq!(ppu.registers[2] & PPUSTATUS_VBLANK, 0);
+        assert_eq!(ppu.nmi_delay, None);
+
+        // Just after the flag is set: it reads as set, but the NMI is dropped.
+        ppu.clock(&pixels);
+        clock_until(&mut ppu, &pixels, VBLANK_START_SCANLINE, 2);
+        assert!(ppu.nmi_delay.is_some());
+        assert_ne!(ppu.read_ppu_status() & PPUSTATUS_VBLANK, 0);
+        assert_eq!(ppu.nmi_delay, None);
+
+        // Without any reads, the NMI goes through.
+        ppu.clock(&pixels);
+        clock_until(&mut ppu, &pixels, VBLANK_START_SCANLINE, 2);
+        assert!(ppu.nmi_delay.is_some());
+        ppu.clock(&pixels);
+        ppu.clock(&pixels);
+        assert_eq!(ppu.nmi_delay, None);
+        assert!(ppu.nmi_output);
+    }
+
+    #[test]
+    fn enabling_nmi_during_vblank_raises_an_nmi() {
+        let pixels = Pixels::new();
+        let mut ppu = test_ppu();
+
+        clock_until(&mut ppu, &pixels, VBLANK_START_SCANLINE + 1, 0);
+        assert_eq!(ppu.nmi_delay, None);
+
+        ppu.write_ppu_ctrl(0b1000_0000);
+        assert!(ppu.nmi_delay.is_some());
+
+        // Toggling the enable bit while the flag stays set raises another one.
+        ppu.clock(&pixels);
+        ppu.clock(&pixels);
+        ppu.write_ppu_ctrl(0);
+        ppu.write_ppu_ctrl(0b1000_0000);
+        assert!(ppu.nmi_delay.is_some());
+
+        // Once the flag has been read, enabling NMIs does nothing.
+        ppu.write_ppu_ctrl(0);
+        ppu.read_ppu_status();
+        ppu.write_ppu_ctrl(0b1000_0000);
+        assert_eq!(ppu.nmi_delay, None);
+    }
+
+    #[test]
+    fn reading_ppu_status_clears_vblank_and_write_latch() {
+        let mut ppu = test_ppu();
+        ppu.registers[2] = PPUSTATUS_VBLANK | PPUSTATUS_SPRITE_ZERO_HIT;
+        ppu.write_ppu_addr(0x3F);
+
+        assert_eq!(
+            ppu.read_ppu_status(),
+            PPUSTATUS_VBLANK | PPUSTATUS_SPRITE_ZERO_HIT
+        );
+        assert_eq!(ppu.read_ppu_status(), PPUSTATUS_SPRITE_ZERO_HIT);
+
+        ppu.write_ppu_addr(0x21);
+        ppu.write_ppu_addr(0x00);
+        assert_eq!(ppu.vram_address, 0x2100);
+    }
+
+    #[test]
+    fn reset_clears_ppu_ctrl_ppu_mask_and_write_latch() {
+        let mut ppu = test_ppu();
+        ppu.write_ppu_ctrl(0b1000_0000);
+        ppu.write_ppu_mask(0b0001_1110);
+        ppu.write_ppu_addr(0x3F);
+
+        ppu.reset();
+        assert_eq!((ppu.registers[0], ppu.registers[1]), (0, 0));
+
+        ppu.write_ppu_addr(0x21);
+        ppu.write_ppu_addr(0x00);
+        assert_eq!(ppu.vram_address, 0x2100);
+    }
+
+    #[test]
+    fn sprite_zero_hit_requires_overlap_outside_clipped_columns() {
+        let pixels = Pixels::new();
+
+        // Tile 1 is fully opaque and covers the whole nametable.
+        let mut character_rom = vec![0; PATTERN_TABLES_SIZE];
+        character_rom[0x10..0x18].fill(0xFF);
+        let mut ppu = ppu_with_character_rom(character_rom);
+        for address in 0x2000..0x23C0 {
+            ppu.write_vram(address, 0x01);
+        }
+
+        // Sprite 0 only overlaps the background within the left 8 pixels.
+        ppu.oam[0..4].copy_from_slice(&[9, 0x01, 0, 0]);
+        ppu.write_ppu_mask(0b0001_1000);
+
+        clock_until(&mut ppu, &pixels, 40, 0);
+        assert_eq!(ppu.registers[2] & PPUSTATUS_SPRITE_ZERO_HIT, 0);
+
+        ppu.write_ppu_mask(0b0001_1110);
+        clock_until(&mut ppu, &pixels, 10, 1);
+        assert_eq!(ppu.registers[2] & PPUSTATUS_SPRITE_ZERO_HIT, 0);
+        clock_until(&mut ppu, &pixels, 10, 2);
+        assert_ne!(ppu.registers[2] & PPUSTATUS_SPRITE_ZERO_HIT, 0);
+
+        clock_until(&mut ppu, &pixels, PRE_RENDER_SCANLINE, 2);
+        assert_eq!(ppu.registers[2] & PPUSTATUS_SPRITE_ZERO_HIT, 0);
+
+        // Sprite 0 only overlaps the background at x=255.
+        ppu.oam[3] = 255;
+        clock_until(&mut ppu, &pixels, 40, 0);
+        assert_eq!(ppu.registers[2] & PPUSTATUS_SPRITE_ZERO_HIT, 0);
+    }
+
+    #[test]
+    fn sprite_overflow_is_set_for_a_ninth_sprite_on_a_scanline() {
+        let mut ppu = test_ppu();
+
+        for sprite in ppu.oam.chunks_mut(4) {
+            sprite[0] = 0xF0;
+        }
+        for sprite in ppu.oam[0..32].chunks_mut(4) {
+            sprite[0] = 50;
+        }
+        ppu.scanline = 50;
+
+        ppu.evaluate_sprites();
+        assert_eq!(ppu.registers[2] & PPUSTATUS_SPRITE_OVERFLOW, 0);
+
+        ppu.oam[32] = 45;
+        ppu.evaluate_sprites();
+        assert_ne!(ppu.registers[2] & PPUSTATUS_SPRITE_OVERFLOW, 0);
+    }
+
+    #[test]
+    fn sprite_overflow_evaluation_reproduces_the_diagonal_oam_bug() {
+        let mut ppu = test_ppu();
+
+        for sprite in ppu.oam.chunks_mut(4) {
+            sprite.copy_from_slice(&[0xF0; 4]);
+        }
+        for sprite in ppu.oam[0..32].chunks_mut(4) {
+            sprite[0] = 50;
+        }
+        // Sprite 10 is on the scanline, but by the time the search reaches it the
+        // hardware compares its attribute byte instead of its Y coordinate.
+        ppu.oam[40] = 50;
+        ppu.scanline = 50;
+
+        ppu.evaluate_sprites();
+        assert_eq!(ppu.registers[2] & PPUSTATUS_SPRITE_OVERFLOW, 0);
+
+        ppu.oam[42] = 50;
+        ppu.evaluate_sprites();
+        assert_ne!(ppu.registers[2] & PPUSTATUS_SPRITE_OVERFLOW, 0);
+    }
+
     #[test]
     fn frame_timing_matches_ntsc_cycle_chart_shape() {
         assert_eq!(DOTS_PER_SCANLINE, 341);
         assert_eq!(VISIBLE_SCANLINES, 240);
-        assert_eq!(POST_RENDER_SCANLINES, 1);
-        assert_eq!(VBLANK_SCANLINES, 20);
+        assert_eq!(Region::Ntsc.post_render_scanlines(), 1);
+        assert_eq!(Region::Ntsc.vblank_scanlines(), 20);
         assert_eq!(PRE_RENDER_SCANLINES, 1);
-        assert_eq!(TOTAL_SCANLINES, 262);
-        assert!((CPU_CYCLES_PER_FRAME - 29780.666666666668).abs() < f64::EPSILON);
+        assert_eq!(Region::Ntsc.total_scanlines(), 262);
+        assert!((Region::Ntsc.cpu_cycles_per_frame() - 29780.666666666668).abs() < f64::EPSILON);
+    }
+
+    fn palette_color(color_index: u8) -> Rgb<u8> {
+        MasterPalette::default().color(color_index, 0)
+    }
+
+    fn clock_until(ppu: &mut Ppu, pixels: &Pixels, scanline: usize, dot: usize) {
+        while !(ppu.scanline == scanline && ppu.dot == dot) {
+            ppu.clock(pixels);
+        }
+    }
+
+    #[test]
+    fn ppu_mask_clips_left_column_and_applies_greyscale() {
+        let pixels = Pixels::new();
+
+        let mut character_rom = vec![0; PATTERN_TABLES_SIZE];
+        character_rom[0x10..0x18].fill(0xFF);
+        let mut ppu = ppu_with_character_rom(character_rom);
+        ppu.write_ppu_mask(0b0000_1001);
+        ppu.write_vram(0x2000, 0x01);
+        ppu.write_vram(0x2001, 0x01);
+        ppu.write_vram(0x3F00, 0x0F);
+        ppu.write_vram(0x3F01, 0x16);
+
+        ppu.scanline = PRE_RENDER_SCANLINE;
+        clock_until(&mut ppu, &pixels, 1, 0);
+
+        assert_eq!(pixels.read(7, 0), palette_color(0x0F & 0x30));
+        assert_eq!(pixels.read(8, 0), palette_color(0x16 & 0x30));
+    }
+
+    #[test]
+    fn disabled_rendering_leaves_v_untouched_and_shows_palette_hack() {
+        let pixels = Pixels::new();
+        let mut ppu = test_ppu();
+        ppu.write_vram(0x3F00, 0x0F);
+        ppu.write_vram(0x3F05, 0x2A);
+
+        ppu.write_ppu_addr(0x3F);
+        ppu.write_ppu_addr(0x05);
+        clock_until(&mut ppu, &pixels, 1, 0);
+
+        assert_eq!(ppu.vram_address, 0x3F05);
+        assert_eq!(pixels.read(100, 0), palette_color(0x2A));
+    }
+
+    #[test]
+    fn scroll_increments_wrap_into_adjacent_nametables() {
+        let mut ppu = test_ppu();
+
+        ppu.vram_address = 0x001F;
+        ppu.increment_coarse_x();
+        assert_eq!(ppu.vram_address, 0x0400);
+
+        // Fine Y 7, coarse Y 29 wraps to the nametable below.
+        ppu.vram_address = 0x73A0;
+        ppu.increment_y();
+        assert_eq!(ppu.vram_address, 0x0800);
+
+        // Coarse Y 31 wraps within the same nametable.
+        ppu.vram_address = 0x73E0;
+        ppu.increment_y();
+        assert_eq!(ppu.vram_address, 0x0000);
+    }
+
+    #[test]
+    fn mid_frame_scroll_writes_split_the_screen() {
+        let pixels = Pixels::new();
+
+        // Tile 1 only has its leftmost column opaque.
+        let mut character_rom = vec![0; PATTERN_TABLES_SIZE];
+        character_rom[0x10..0x18].fill(0b1000_0000);
+        let mut ppu = ppu_with_character_rom(character_rom);
+        ppu.write_ppu_mask(0b0001_1110);
+        ppu.write_vram(0x3F01, 0x16);
+        // The top-left tile of the first nametable, and the tile at row 2,
+        // column 1 of the second nametable.
+        ppu.write_vram(0x2000, 0x01);
+        ppu.write_vram(0x2441, 0x01);
+
+        ppu.write_ppu_scroll(0);
+        ppu.write_ppu_scroll(0);
+        ppu.scanline = PRE_RENDER_SCANLINE;
+        clock_until(&mut ppu, &pixels, 1, 0);
+        assert_eq!(pixels.read(0, 0), palette_color(0x16));
+        assert_eq!(pixels.read(1, 0), palette_color(0x00));
+
+        // A status bar style split: point v at coarse Y 2 of the second nametable,
+        // then fine scroll 3 pixels to the right.
+        clock_until(&mut ppu, &pixels, 9, 260);
+        ppu.write_ppu_addr(0x04);
+        ppu.write_ppu_addr(0x40);
+        ppu.write_ppu_scroll(11);
+
+        clock_until(&mut ppu, &pixels, 11, 0);
+        assert_eq!(pixels.read(5, 10), palette_color(0x16));
+        assert_eq!(pixels.read(8, 10), palette_color(0x00));
+    }
+
+    #[test]
+    fn oam_data_writes_increment_oam_addr() {
+        let mut ppu = test_ppu();
+
+        ppu.write_oam_addr(0xFE);
+        ppu.write_oam_data(0x12);
+        ppu.write_oam_data(0x34);
+        ppu.write_oam_data(0x56);
+
+        assert_eq!(ppu.oam[0xFE], 0x12);
+        assert_eq!(ppu.oam[0xFF], 0x34);
+        assert_eq!(ppu.oam[0x00], 0x56);
+        assert_eq!(ppu.read_oam_data(), ppu.oam[0x01]);
+    }
+
+    #[test]
+    fn sprites_render_flipped_with_their_palette_on_the_next_scanline() {
+        let pixels = Pixels::new();
+
+        // Tile 1 has its leftmost column set to value 1 on every row.
+        let mut character_rom = vec![0; PATTERN_TABLES_SIZE];
+        character_rom[0x10..0x18].fill(0b1000_0000);
+        let mut ppu = ppu_with_character_rom(character_rom);
+        ppu.write_ppu_mask(0b0001_1110);
+        ppu.write_vram(0x3F00, 0x0F);
+        ppu.write_vram(0x3F15, 0x27);
+
+        // Sprite 0 sits at (10, 20), flipped horizontally with palette 5.
+        ppu.oam[0..4].copy_from_slice(&[19, 0x01, 0b0100_0001, 10]);
+
+        clock_until(&mut ppu, &pixels, 20, 20);
+        assert_eq!(pixels.read(10, 20), palette_color(0x0F));
+        assert_eq!(pixels.read(17, 20), palette_color(0x27));
+
+        clock_until(&mut ppu, &pixels, 19, 20);
+        assert_eq!(pixels.read(17, 19), palette_color(0x0F));
+    }
+
+    #[test]
+    fn tall_sprites_take_their_pattern_table_from_the_tile_index() {
+        let pixels = Pixels::new();
+
+        // The bottom half of the 8x16 sprite made of tiles $02/$03 in the right pattern table.
+        let mut character_rom = vec![0; PATTERN_TABLES_SIZE];
+        character_rom[0x1030..0x1038].fill(0b1000_0000);
+        let mut ppu = ppu_with_character_rom(character_rom);
+        ppu.write_ppu_mask(0b0001_1110);
+        ppu.write_vram(0x3F11, 0x16);
+        ppu.write_ppu_ctrl(0b0010_0000);
+
+        ppu.oam[0..4].copy_from_slice(&[0, 0x03, 0, 0]);
+
+        clock_until(&mut ppu, &pixels, 9, 2);
+        assert_eq!(pixels.read(0, 8), palette_color(0x00));
+        assert_eq!(pixels.read(0, 9), palette_color(0x16));
+    }
+
+    #[test]
+    fn ppu_addr_latch_and_data_increment_by_ppuctrl() {
+        let mut ppu = test_ppu();
+
+        ppu.write_ppu_addr(0x21);
+        ppu.write_ppu_addr(0x08);
+        ppu.write_ppu_data(0xAA);
+        ppu.write_ppu_data(0xBB);
+
+        assert_eq!(ppu.read_vram(0x2108), 0xAA);
+        assert_eq!(ppu.read_vram(0x2109), 0xBB);
+        assert_eq!(ppu.vram_address, 0x210A);
+
+        ppu.write_ppu_ctrl(0b0000_0100);
+        ppu.write_ppu_addr(0x20);
+        ppu.write_ppu_addr(0x00);
+        ppu.write_ppu_data(0x11);
+        ppu.write_ppu_data(0x22);
+
+        assert_eq!(ppu.read_vram(0x2000), 0x11);
+        assert_eq!(ppu.read_vram(0x2020), 0x22);
+        assert_eq!(ppu.vram_address, 0x2040);
+    }
+
+    #[test]
+    fn nametables_follow_the_cartridge_mirroring() {
+        let mut ppu = test_ppu();
+
+        ppu.set_mirroring(Mirroring::Horizontal);
+        ppu.write_vram(0x2005, 0x11);
+        ppu.write_vram(0x2C05, 0x22);
+        assert_eq!(ppu.read_vram(0x2405), 0x11);
+        assert_eq!(ppu.read_vram(0x2805), 0x22);
+
+        ppu.set_mirroring(Mirroring::Vertical);
+        assert_eq!(ppu.read_vram(0x2805), 0x11);
+        assert_eq!(ppu.read_vram(0x2405), 0x22);
+
+        ppu.set_mirroring(Mirroring::SingleScreenUpper);
+        assert_eq!(ppu.read_vram(0x2005), 0x22);
+        assert_eq!(ppu.read_vram(0x3C05), 0x22);
+
+        ppu.set_mirroring(Mirroring::FourScreen);
+        ppu.write_vram(0x2C05, 0x33);
+        assert_eq!(ppu.read_vram(0x2405), 0x22);
+        assert_eq!(ppu.read_vram(0x2C05), 0x33);
+    }
+
+    #[test]
+    fn sprite_fetches_from_the_upper_pattern_table_clock_the_mmc3_irq_counter() {
+        let pixels = Pixels::new();
+        let ines = Ines {
+            header: Header {
+                character_rom_size_multiplier: 1,
+                mapper_number: 4,
+                ..Header::default()
+            },
+            ..Ines::default()
+        };
+        let cartridge = Rc::new(RefCell::new(Cartridge::new(ines)));
+        let mut ppu = test_ppu();
+        ppu.cartridge = Some(cartridge.clone());
+
+        // Background at $0000 and sprites at $1000.
+        ppu.write_ppu_ctrl(0b0000_1000);
+        ppu.write_ppu_mask(0b0001_1000);
+        cartridge.borrow_mut().write(0xC000, 3);
+        cartridge.borrow_mut().write(0xC001, 0);
+        cartridge.borrow_mut().write(0xE001, 0);
+
+        ppu.scanline = PRE_RENDER_SCANLINE;
+        while !cartridge.borrow().irq() {
+            ppu.clock(&pixels);
+        }
+
+        // The pre-render scanline reloads the counter, and the next three count it down.
+        assert_eq!(ppu.scanline, 2);
+        assert!(matches!(ppu.dot, 257..=320));
+    }
+
+    #[test]
+    fn ppu_data_reads_are_buffered_except_for_palettes() {
+        let mut ppu = test_ppu();
+        ppu.write_vram(0x2400, 0x12);
+        ppu.write_vram(0x2F00, 0x34);
+        ppu.write_vram(0x3F00, 0x0F);
+
+        ppu.write_ppu_addr(0x24);
+        ppu.write_ppu_addr(0x00);
+        assert_eq!(ppu.read_ppu_data(), 0x00);
+        assert_eq!(ppu.read_ppu_data(), 0x12);
+
+        ppu.write_ppu_addr(0x3F);
+        ppu.write_ppu_addr(0x00);
+        assert_eq!(ppu.read_ppu_data(), 0x0F);
+        assert_eq!(ppu.read_buffer, 0x34);
     }
 }
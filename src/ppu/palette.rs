@@ -0,0 +1,264 @@
+use clap::ValueEnum;
+use rgb::Rgb;
+use std::fmt;
+
+pub(super) const PALETTE_RAM_SIZE: usize = 32;
+const COLORS: usize = 64;
+const EMPHASIS_COMBINATIONS: usize = 8;
+const BYTES_PER_COLOR: usize = 3;
+/// How much each emphasis bit darkens the two color channels it doesn't emphasize,
+/// for palettes that don't come with their own emphasized colors.
+const EMPHASIS_ATTENUATION: f32 = 0.816328;
+
+/// The [2C02](https://www.nesdev.org/wiki/PPU_palettes#2C02) palette of the NTSC PPU.
+const RP2C02_PALETTE: [u32; COLORS] = [
+    0x626262, 0x001FB2, 0x2404C8, 0x5200B2, 0x730076, 0x800024, 0x730B00, 0x522800, 0x244400,
+    0x005700, 0x005C00, 0x005324, 0x003C76, 0x000000, 0x000000, 0x000000, 0xABABAB, 0x0D57FF,
+    0x4B30FF, 0x8A13FF, 0xBC08D6, 0xD21269, 0xC72E00, 0x9D5400, 0x607B00, 0x209800, 0x00A300,
+    0x009942, 0x007DB4, 0x000000, 0x000000, 0x000000, 0xFFFFFF, 0x53AEFF, 0x9085FF, 0xD365FF,
+    0xFF57FF, 0xFF5DCF, 0xFF7757, 0xFA9E00, 0xBDC700, 0x7AE700, 0x43F611, 0x26EF7E, 0x2CD5F6,
+    0x4E4E4E, 0x000000, 0x000000, 0xFFFFFF, 0xB6E1FF, 0xCED1FF, 0xE9C3FF, 0xFFBCFF, 0xFFBDF4,
+    0xFFC6C3, 0xFFD59A, 0xE9E681, 0xCEF481, 0xB6FB9A, 0xA9FAC3, 0xA9F0F4, 0xB8B8B8, 0x000000,
+    0x000000,
+];
+
+/// Uses the [RP2C03](https://www.nesdev.org/wiki/PPU_palettes#2C03_and_2C05) palette of the
+/// RGB PPU, where each decimal digit holds a 3-bit red, green and blue level.
+#[allow(clippy::zero_prefixed_literal)]
+const RP2C03_PALETTE: [u16; COLORS] = [
+    333, 014, 006, 326, 403, 503, 510, 420, 320, 120, 031, 040, 022, 000, 000, 000, 555, 036, 027,
+    407, 507, 704, 700, 630, 430, 140, 040, 053, 044, 000, 000, 000, 777, 357, 447, 637, 707, 737,
+    740, 750, 660, 360, 070, 276, 077, 000, 000, 000, 777, 567, 657, 757, 747, 755, 764, 772, 773,
+    572, 473, 276, 467, 000, 000, 000,
+];
+
+/// Uses the [RP2C04-0004](https://www.nesdev.org/wiki/PPU_palettes#RP2C04-0004) palette, in
+/// the same format as [`RP2C03_PALETTE`].
+#[allow(clippy::zero_prefixed_literal)]
+const RP2C04_PALETTE: [u16; COLORS] = [
+    430, 326, 044, 660, 000, 755, 014, 630, 555, 310, 070, 003, 764, 770, 040, 572, 737, 200, 027,
+    747, 000, 222, 510, 740, 653, 053, 447, 140, 403, 000, 473, 357, 503, 031, 420, 006, 407, 507,
+    333, 704, 022, 666, 036, 020, 111, 773, 444, 707, 757, 777, 320, 700, 760, 276, 777, 467, 000,
+    750, 637, 567, 360, 657, 077, 120,
+];
+
+/// The built-in master palettes that can be selected from the command line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PaletteVariant {
+    /// The NTSC PPU found in the NES and Famicom.
+    #[default]
+    #[value(name = "2c02")]
+    Rp2c02,
+    /// The RGB PPU found in the PlayChoice-10 and Famicom Titler.
+    #[value(name = "2c03")]
+    Rp2c03,
+    /// The RGB PPU found in Vs. System arcade boards (RP2C04-0004).
+    #[value(name = "2c04")]
+    Rp2c04,
+}
+
+/// Returned when a `.pal` file isn't 192 bytes (64 colors) or 1536 bytes
+/// (64 colors for each of the 8 color emphasis combinations).
+#[derive(Debug)]
+pub struct InvalidPaletteFile {
+    pub length: usize,
+}
+
+impl fmt::Display for InvalidPaletteFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "palette files must be {} or {} bytes long, but this one is {} bytes",
+            COLORS * BYTES_PER_COLOR,
+            COLORS * EMPHASIS_COMBINATIONS * BYTES_PER_COLOR,
+            self.length
+        )
+    }
+}
+
+impl std::error::Error for InvalidPaletteFile {}
+
+/// Maps the 6-bit color indexes stored in palette RAM to RGB. It holds 64 colors
+/// for each of the 8 combinations of the PPUMASK color emphasis bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MasterPalette {
+    colors: Vec<Rgb<u8>>,
+}
+
+impl Default for MasterPalette {
+    fn default() -> Self {
+        Self::builtin(PaletteVariant::default())
+    }
+}
+
+impl MasterPalette {
+    pub fn builtin(variant: PaletteVariant) -> Self {
+        let colors = match variant {
+            PaletteVariant::Rp2c02 => RP2C02_PALETTE
+                .iter()
+                .map(|rgb| Rgb {
+                    r: (rgb >> 16) as u8,
+                    g: (rgb >> 8) as u8,
+                    b: *rgb as u8,
+                })
+                .collect(),
+            PaletteVariant::Rp2c03 => decode_rgb_levels(&RP2C03_PALETTE),
+            PaletteVariant::Rp2c04 => decode_rgb_levels(&RP2C04_PALETTE),
+        };
+
+        Self {
+            colors: with_generated_emphasis(colors),
+        }
+    }
+
+    /// Loads a palette from the contents of a standard `.pal` file, which holds
+    /// 3 bytes of RGB for each color. Files with only 64 colors have their
+    /// emphasized colors generated.
+    pub fn from_pal_file(bytes: &[u8]) -> Result<Self, InvalidPaletteFile> {
+        if bytes.len() != COLORS * BYTES_PER_COLOR
+            && bytes.len() != COLORS * EMPHASIS_COMBINATIONS * BYTES_PER_COLOR
+        {
+            return Err(InvalidPaletteFile {
+                length: bytes.len(),
+            });
+        }
+
+        let colors = bytes
+            .chunks(BYTES_PER_COLOR)
+            .map(|rgb| Rgb {
+                r: rgb[0],
+                g: rgb[1],
+                b: rgb[2],
+            })
+            .collect::<Vec<_>>();
+
+        let colors = match colors.len() {
+            COLORS => with_generated_emphasis(colors),
+            _ => colors,
+        };
+
+        Ok(Self { colors })
+    }
+
+    /// Returns the color for a color index, where `emphasis` is the code
+    /// returned by [`PpuMask::color_emphasis()`](super::PpuMask::color_emphasis).
+    pub fn color(&self, color_index: u8, emphasis: u8) -> Rgb<u8> {
+        self.colors[(emphasis & 0b111) as usize * COLORS + (color_index & 0x3F) as usize]
+    }
+}
+
+/// Extends 64 colors to all 8 emphasis combinations. Every emphasized channel
+/// darkens the other two, so emphasizing all three darkens the whole picture.
+fn with_generated_emphasis(colors: Vec<Rgb<u8>>) -> Vec<Rgb<u8>> {
+    (0..EMPHASIS_COMBINATIONS as u8)
+        .flat_map(|emphasis| {
+            let attenuation = |channel_bit: u8| {
+                (0..3)
+                    .filter(|bit| *bit != channel_bit && emphasis & (1 << bit) != 0)
+                    .fold(1.0, |factor, _| factor * EMPHASIS_ATTENUATION)
+            };
+            let (red, green, blue) = (attenuation(0), attenuation(1), attenuation(2));
+
+            colors.iter().map(move |color| Rgb {
+                r: (color.r as f32 * red) as u8,
+                g: (color.g as f32 * green) as u8,
+                b: (color.b as f32 * blue) as u8,
+            })
+        })
+        .collect()
+}
+
+fn decode_rgb_levels(palette: &[u16; COLORS]) -> Vec<Rgb<u8>> {
+    let scale = |level: u16| (level * 255 / 7) as u8;
+
+    palette
+        .iter()
+        .map(|levels| Rgb {
+            r: scale(levels / 100),
+            g: scale((levels / 10) % 10),
+            b: scale(levels % 10),
+        })
+        .collect()
+}
+
+/// Folds a palette address onto palette RAM. The backdrop entries of the sprite
+/// palettes ($3F10/$3F14/$3F18/$3F1C) are mirrors of the background ones.
+pub(super) fn palette_ram_index(address: u16) -> usize {
+    let index = address as usize % PALETTE_RAM_SIZE;
+
+    match index {
+        0x10 | 0x14 | 0x18 | 0x1C => index - 0x10,
+        _ => index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprite_backdrop_entries_mirror_background_entries() {
+        assert_eq!(palette_ram_index(0x3F10), 0x00);
+        assert_eq!(palette_ram_index(0x3F1C), 0x0C);
+        assert_eq!(palette_ram_index(0x3F11), 0x11);
+        assert_eq!(palette_ram_index(0x3F34), 0x04);
+    }
+
+    #[test]
+    fn pal_files_must_hold_64_or_512_colors() {
+        let mut bytes = vec![0; 192];
+        bytes[3..6].copy_from_slice(&[0x12, 0x34, 0x56]);
+
+        let palette = MasterPalette::from_pal_file(&bytes).unwrap();
+        assert_eq!(
+            palette.color(0x01, 0),
+            Rgb {
+                r: 0x12,
+                g: 0x34,
+                b: 0x56
+            }
+        );
+
+        assert!(MasterPalette::from_pal_file(&[0; 1536]).is_ok());
+        assert_eq!(
+            MasterPalette::from_pal_file(&[0; 193]).unwrap_err().length,
+            193
+        );
+    }
+
+    #[test]
+    fn emphasis_darkens_the_other_channels() {
+        let palette = MasterPalette::builtin(PaletteVariant::Rp2c02);
+        let white = palette.color(0x30, 0);
+
+        let red_emphasis = palette.color(0x30, 0b001);
+        assert_eq!(red_emphasis.r, white.r);
+        assert!(red_emphasis.g < white.g && red_emphasis.b < white.b);
+
+        let all_emphasis = palette.color(0x30, 0b111);
+        assert!(all_emphasis.r < red_emphasis.r && all_emphasis.g < red_emphasis.g);
+    }
+
+    #[test]
+    fn rgb_ppu_palettes_scale_3_bit_levels() {
+        let palette = MasterPalette::builtin(PaletteVariant::Rp2c03);
+
+        assert_eq!(
+            palette.color(0x30, 0),
+            Rgb {
+                r: 255,
+                g: 255,
+                b: 255
+            }
+        );
+        assert_eq!(palette.color(0x0D, 0), Rgb { r: 0, g: 0, b: 0 });
+        assert_eq!(
+            palette.color(0x01, 0),
+            Rgb {
+                r: 0,
+                g: 36,
+                b: 145
+            }
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use super::{Ppu, PPUSTATUS_SPRITE_OVERFLOW};
+
+pub(super) const OAM_SIZE: usize = 256;
+const SECONDARY_OAM_SIZE: usize = 32;
+const MAX_SPRITES_PER_SCANLINE: usize = 8;
+const BYTES_PER_SPRITE: usize = 4;
+
+const ATTRIBUTE_PALETTE: u8 = 0b0000_0011;
+const ATTRIBUTE_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const ATTRIBUTE_FLIP_HORIZONTALLY: u8 = 0b0100_0000;
+const ATTRIBUTE_FLIP_VERTICALLY: u8 = 0b1000_0000;
+
+/// A sprite that was fetched during dots 257-320 to be drawn on the next scanline.
+#[derive(Clone, Copy, Default)]
+struct SpriteSlot {
+    x: u8,
+    attributes: u8,
+    /// The pattern bits are stored already flipped, so the leftmost pixel is always bit 7.
+    pattern_low: u8,
+    pattern_high: u8,
+}
+
+/// A sprite pixel, as it comes out of the sprite multiplexer.
+#[derive(Clone, Copy)]
+pub(super) struct SpritePixel {
+    /// The palette number (4-7).
+    pub palette: u8,
+    /// The pixel value (1-3). Transparent pixels are never output.
+    pub pixel: u8,
+    pub behind_background: bool,
+    pub is_sprite_zero: bool,
+}
+
+pub(super) struct SpritePipeline {
+    secondary_oam: [u8; SECONDARY_OAM_SIZE],
+    /// The amount of sprites copied into secondary OAM for the next scanline.
+    sprite_count: usize,
+    /// Whether sprite 0 was copied into secondary OAM, in which case it is always the first slot.
+    sprite_zero_present: bool,
+    slots: [SpriteSlot; MAX_SPRITES_PER_SCANLINE],
+}
+
+impl Default for SpritePipeline {
+    fn default() -> Self {
+        Self {
+            secondary_oam: [0xFF; SECONDARY_OAM_SIZE],
+            sprite_count: 0,
+            sprite_zero_present: false,
+            slots: [SpriteSlot::default(); MAX_SPRITES_PER_SCANLINE],
+        }
+    }
+}
+
+impl SpritePipeline {
+    /// Returns the first opaque sprite pixel at `x`. Sprites earlier in OAM win.
+    pub(super) fn output(&self, x: usize) -> Option<SpritePixel> {
+        self.slots.iter().enumerate().find_map(|(index, slot)| {
+            let column = x
+                .checked_sub(slot.x as usize)
+                .filter(|column| *column < 8)?;
+
+            let bit = |pattern: u8| (pattern >> (7 - column)) & 1;
+            let pixel = bit(slot.pattern_low) | (bit(slot.pattern_high) << 1);
+
+            (pixel != 0).then_some(SpritePixel {
+                palette: 4 + (slot.attributes & ATTRIBUTE_PALETTE),
+                pixel,
+                behind_background: slot.attributes & ATTRIBUTE_BEHIND_BACKGROUND != 0,
+                is_sprite_zero: index == 0 && self.sprite_zero_present,
+            })
+        })
+    }
+}
+
+impl Ppu {
+    /// Runs sprite evaluation and the sprite pattern fetches for the current dot.
+    /// This is only called on the visible and pre-render scanlines.
+    pub(super) fn clock_sprites(&mut self) {
+        if self.dot == 257 {
+            self.evaluate_sprites();
+        }
+
+        if matches!(self.dot, 257..=320) {
+            // OAMADDR is reset during each sprite tile loading interval.
+            self.oam_address = 0;
+
+            let slot = (self.dot - 257) / 8;
+            match (self.dot - 257) % 8 {
+                4 => self.fetch_sprite_pattern(slot, false),
+                6 => self.fetch_sprite_pattern(slot, true),
+                _ => {}
+            }
+        }
+    }
+
+    fn sprite_height(&self) -> usize {
+        match self.ppu_status.sprite_size() {
+            0 => 8,
+            _ => 16,
+        }
+    }
+
+    /// Copies the first 8 sprites in OAM that fall on the current scanline into
+    /// secondary OAM. They are drawn on the following scanline, which is why a
+    /// sprite's Y coordinate is one less than the scanline it first appears on.
+    ///
+    /// Once secondary OAM is full, the search for a 9th sprite to set the sprite
+    /// overflow flag with reproduces the hardware bug where the byte offset within
+    /// each sprite is incremented along with the sprite index, so that tile, attribute
+    /// and X bytes are wrongly compared as Y coordinates.
+    pub(super) fn evaluate_sprites(&mut self) {
+        self.sprites.secondary_oam.fill(0xFF);
+        self.sprites.sprite_count = 0;
+        self.sprites.sprite_zero_present = false;
+
+        if self.scanline == self.region.pre_render_scanline() {
+            return;
+        }
+
+        let height = self.sprite_height();
+        let in_range = |y: u8| self.scanline.wrapping_sub(y as usize) < height;
+
+        let mut sprite_index = 0;
+
+        while sprite_index < OAM_SIZE / BYTES_PER_SPRITE
+            && self.sprites.sprite_count < MAX_SPRITES_PER_SCANLINE
+        {
+            let start = sprite_index * BYTES_PER_SPRITE;
+            let sprite = &self.oam[start..start + BYTES_PER_SPRITE];
+
+            if in_range(sprite[0]) {
+                let destination = self.sprites.sprite_count * BYTES_PER_SPRITE;
+                self.sprites.secondary_oam[destination..destination + BYTES_PER_SPRITE]
+                    .copy_from_slice(sprite);
+                self.sprites.sprite_count += 1;
+                self.sprites.sprite_zero_present |= sprite_index == 0;
+            }
+
+            sprite_index += 1;
+        }
+
+        let mut byte_offset = 0;
+
+        while sprite_index < OAM_SIZE / BYTES_PER_SPRITE {
+            if in_range(self.oam[sprite_index * BYTES_PER_SPRITE + byte_offset]) {
+                self.registers[2] |= PPUSTATUS_SPRITE_OVERFLOW;
+                break;
+            }
+
+            sprite_index += 1;
+            byte_offset = (byte_offset + 1) % BYTES_PER_SPRITE;
+        }
+    }
+
+    fn fetch_sprite_pattern(&mut self, slot: usize, high_plane: bool) {
+        let start = slot * BYTES_PER_SPRITE;
+        let sprite: [u8; BYTES_PER_SPRITE] = self.sprites.secondary_oam
+            [start..start + BYTES_PER_SPRITE]
+            .try_into()
+            .unwrap();
+        let [y, tile, attributes, x] = sprite;
+
+        let height = self.sprite_height();
+        let mut row = self.scanline.wrapping_sub(y as usize) % height;
+
+        if attributes & ATTRIBUTE_FLIP_VERTICALLY != 0 {
+            row = height - 1 - row;
+        }
+
+        // 8x16 sprites take their pattern table from bit 0 of the tile index, and
+        // are made of an even top tile and the odd tile after it.
+        let (pattern_table, tile) = match height {
+            8 => (self.ppu_status.sprite_pattern_table_address() as u16, tile),
+            _ => ((tile & 1) as u16, (tile & 0xFE) + u8::from(row >= 8)),
+        };
+
+        let address = (pattern_table << 12)
+            + ((tile as u16) << 4)
+            + (row % 8) as u16
+            + if high_plane { 8 } else { 0 };
+        let mut pattern = self.read_vram(address);
+
+        // Unused slots are still fetched (with tile $FF), but always stay transparent.
+        if slot >= self.sprites.sprite_count {
+            pattern = 0;
+        }
+
+        if attributes & ATTRIBUTE_FLIP_HORIZONTALLY != 0 {
+            pattern = pattern.reverse_bits();
+        }
+
+        let slot = &mut self.sprites.slots[slot];
+        slot.x = x;
+        slot.attributes = attributes;
+
+        match high_plane {
+            true => slot.pattern_high = pattern,
+            false => slot.pattern_low = pattern,
+        }
+    }
+}
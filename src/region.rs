@@ -0,0 +1,115 @@
+use crate::ppu::{DOTS_PER_SCANLINE, PRE_RENDER_SCANLINES, VISIBLE_SCANLINES};
+use clap::ValueEnum;
+
+/// The console variants, which differ in their clock speeds and frame layout.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default, ValueEnum)]
+pub enum Region {
+    /// The NTSC NES and Famicom (RP2A03 + RP2C02).
+    #[default]
+    Ntsc,
+    /// The PAL NES (RP2A07 + RP2C07).
+    Pal,
+    /// Dendy and other PAL famiclones (UA6527P + UA6538).
+    Dendy,
+}
+
+impl Region {
+    pub const fn master_clock_hz(self) -> f64 {
+        match self {
+            Region::Ntsc => 21_477_272.0,
+            Region::Pal | Region::Dendy => 26_601_712.0,
+        }
+    }
+
+    /// Master clock cycles per CPU cycle.
+    pub const fn cpu_clock_divisor(self) -> u64 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    /// Master clock cycles per PPU dot.
+    pub const fn ppu_clock_divisor(self) -> u64 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    pub fn cpu_hz(self) -> f64 {
+        self.master_clock_hz() / self.cpu_clock_divisor() as f64
+    }
+
+    /// 3 on NTSC and Dendy, but 3.2 on PAL.
+    pub fn ppu_dots_per_cpu_cycle(self) -> f64 {
+        self.cpu_clock_divisor() as f64 / self.ppu_clock_divisor() as f64
+    }
+
+    /// The idle scanlines between the visible scanlines and the start of vblank.
+    /// Dendy delays vblank by 50 extra scanlines so that it lasts as long as on
+    /// NTSC, which keeps the NTSC timing of most games intact.
+    pub const fn post_render_scanlines(self) -> usize {
+        match self {
+            Region::Ntsc | Region::Pal => 1,
+            Region::Dendy => 51,
+        }
+    }
+
+    pub const fn vblank_scanlines(self) -> usize {
+        match self {
+            Region::Ntsc | Region::Dendy => 20,
+            Region::Pal => 70,
+        }
+    }
+
+    pub const fn total_scanlines(self) -> usize {
+        VISIBLE_SCANLINES
+            + self.post_render_scanlines()
+            + self.vblank_scanlines()
+            + PRE_RENDER_SCANLINES
+    }
+
+    pub const fn vblank_start_scanline(self) -> usize {
+        VISIBLE_SCANLINES + self.post_render_scanlines()
+    }
+
+    pub const fn pre_render_scanline(self) -> usize {
+        self.total_scanlines() - 1
+    }
+
+    /// Only the NTSC PPU shortens odd frames by a dot while rendering.
+    pub const fn skips_odd_frame_dot(self) -> bool {
+        matches!(self, Region::Ntsc)
+    }
+
+    pub fn cpu_cycles_per_frame(self) -> f64 {
+        (DOTS_PER_SCANLINE * self.total_scanlines()) as f64 / self.ppu_dots_per_cpu_cycle()
+    }
+
+    pub fn frame_interval_secs(self) -> f64 {
+        self.cpu_cycles_per_frame() / self.cpu_hz()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pal_runs_3_2_ppu_dots_per_cpu_cycle() {
+        assert_eq!(Region::Pal.ppu_dots_per_cpu_cycle(), 3.2);
+        assert_eq!(Region::Dendy.ppu_dots_per_cpu_cycle(), 3.0);
+        assert_eq!(Region::Pal.cpu_cycles_per_frame(), 33247.5);
+    }
+
+    #[test]
+    fn pal_and_dendy_frames_have_312_scanlines() {
+        assert_eq!(Region::Pal.total_scanlines(), 312);
+        assert_eq!(Region::Pal.vblank_start_scanline(), 241);
+        assert_eq!(Region::Dendy.total_scanlines(), 312);
+        assert_eq!(Region::Dendy.vblank_start_scanline(), 291);
+        assert_eq!(Region::Dendy.pre_render_scanline(), 311);
+    }
+}
@@ -1,33 +1,68 @@
+use crate::apu::Apu;
+use crate::audio::{AudioOptions, AudioSink, NullSink, Resampler};
 use crate::cartridge::Cartridge;
+use crate::config::{Config, Hotkeys};
+use crate::controller::{Buttons, Controllers};
 use crate::cpu::{CpuContainer, CpuDebugSnapshot};
 use crate::debug::StartupInstructionTrace;
-use crate::display::{Pixels, HEIGHT};
+use crate::display::{Pixels, HEIGHT, WIDTH};
+#[cfg(feature = "gamepad")]
+use crate::gamepad::Gamepads;
 use crate::graphical_debug::{draw_app_frame, ColorToggles, APP_WIDTH};
-use crate::ppu::{self, Ppu, PpuDebugSnapshot};
-use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+use crate::ppu::{Ppu, PpuDebugSnapshot};
+use crate::region::Region;
+use clap::ValueEnum;
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Scale, Window, WindowOptions};
+use nes6502::Interrupts;
 use std::cell::RefCell;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use std::thread::spawn;
-use std::time::Instant;
+use std::thread::{spawn, JoinHandle};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-const MASTER_CLOCK_HZ: f64 = 21_477_272.0;
-const CLOCK_DIVISOR: u64 = 12;
-const CPU_HZ: f64 = MASTER_CLOCK_HZ / CLOCK_DIVISOR as f64;
-const FRAME_INTERVAL_SECS: f64 = ppu::CPU_CYCLES_PER_FRAME / CPU_HZ;
-const PPU_CLOCK_DIVISOR: u8 = 4;
+/// How much audio the emulator tries to keep queued when pacing itself off the
+/// audio sink.
+const AUDIO_LATENCY_SECS: f64 = 0.05;
 
-#[derive(Default, Debug)]
-enum Keycode {
+/// How many times faster than normal the emulator runs while fast-forwarding.
+const FAST_FORWARD_SPEED: f64 = 4.0;
+
+/// What the emulator thread uses to decide how many cycles to run per frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Pacing {
+    /// Follow the time between rendered frames.
     #[default]
-    Placeholder,
+    Video,
+    /// Keep the audio sink's buffer filled to a steady level. Sinks that don't
+    /// play in real time fall back to video pacing.
+    Audio,
+}
+
+pub struct RuntimeOptions {
+    pub region: Region,
+    pub config: Config,
+    pub audio: AudioOptions,
+    pub pacing: Pacing,
+}
+
+#[derive(Debug)]
+enum Keycode {
     ToggleOrange,
     ToggleIndigo,
+    TogglePause,
+    Reset,
 }
 
 #[derive(Debug)]
 struct FrameFinishedSignal {
-    current_keycode: Keycode,
+    /// The hotkeys pressed this frame, in the order they are handled.
+    keycodes: Vec<Keycode>,
+    controller_buttons: [Buttons; 2],
+    zapper_trigger: bool,
+    /// The pixel under the mouse, or `None` when it is outside the picture.
+    zapper_aim: Option<(usize, usize)>,
+    fast_forward: bool,
     delay_debt_s: f64,
 }
 
@@ -45,35 +80,49 @@ impl SharedDebug {
     }
 }
 
-pub fn run<F>(create_emulator: F) -> Result<(), Box<dyn std::error::Error>>
+pub fn run<F>(options: RuntimeOptions, create_emulator: F) -> Result<(), Box<dyn std::error::Error>>
 where
-    F: FnOnce() -> Emulator + Send + 'static,
+    F: FnOnce(&Arc<Pixels>) -> Emulator + Send + 'static,
 {
+    let region = options.region;
+    let config = options.config.clone();
     let pixels = Arc::new(Pixels::new());
     let shared_debug = SharedDebug::new();
     let mut buffer = vec![0; APP_WIDTH * HEIGHT];
     let (tx, rx) = crossbeam_channel::unbounded::<FrameFinishedSignal>();
 
-    spawn_emulator(create_emulator, rx, pixels.clone(), &shared_debug);
-    run_render_loop(pixels, shared_debug, &mut buffer, tx)?;
+    let emulator_thread =
+        spawn_emulator(options, create_emulator, rx, pixels.clone(), &shared_debug);
+    run_render_loop(region, &config, pixels, shared_debug, &mut buffer, tx)?;
+
+    // Wait for the emulator to shut down, so that the audio sink can finish up.
+    emulator_thread
+        .join()
+        .map_err(|_| "the emulator thread panicked")?;
 
     Ok(())
 }
 
 fn spawn_emulator<F>(
+    options: RuntimeOptions,
     create_emulator: F,
     rx: crossbeam_channel::Receiver<FrameFinishedSignal>,
     pixels: Arc<Pixels>,
     shared_debug: &SharedDebug,
-) where
-    F: FnOnce() -> Emulator + Send + 'static,
+) -> JoinHandle<()>
+where
+    F: FnOnce(&Arc<Pixels>) -> Emulator + Send + 'static,
 {
     let cpu_debug = shared_debug.cpu.clone();
     let ppu_debug = shared_debug.ppu.clone();
 
     spawn(move || {
-        let mut emulator = create_emulator();
-        let mut runner = EmulatorRunner::new();
+        let mut emulator = create_emulator(&pixels);
+        let audio_sink = options.audio.open_sink().unwrap_or_else(|err| {
+            eprintln!("Failed to open the audio output, continuing without audio: {err}");
+            Box::new(NullSink::new(options.audio.sample_rate))
+        });
+        let mut runner = EmulatorRunner::new(options.region, options.pacing, audio_sink);
 
         while let Ok(frame_finished_signal) = rx.recv() {
             runner.run_frame(
@@ -84,10 +133,12 @@ fn spawn_emulator<F>(
                 frame_finished_signal,
             );
         }
-    });
+    })
 }
 
 fn run_render_loop(
+    region: Region,
+    config: &Config,
     pixels: Arc<Pixels>,
     shared_debug: SharedDebug,
     buffer: &mut [u32],
@@ -103,13 +154,18 @@ fn run_render_loop(
         },
     )?;
 
-    window.set_target_fps(60);
+    window.set_target_fps(region.frame_interval_secs().recip().round() as usize);
 
     let mut previous_frame_stamp = Instant::now();
     let mut color_toggles = ColorToggles::default();
+    let mut controller_input = ControllerInput::new();
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        let current_keycode = process_input(&window, &mut color_toggles);
+        let keycodes = process_input(&window, &config.hotkeys, &mut color_toggles);
+
+        if window.is_key_pressed(config.hotkeys.screenshot.0, KeyRepeat::No) {
+            save_screenshot(&pixels);
+        }
 
         draw_app_frame(
             buffer,
@@ -120,9 +176,16 @@ fn run_render_loop(
         );
         window.update_with_buffer(buffer, APP_WIDTH, HEIGHT)?;
 
-        let delay_debt_s = previous_frame_stamp.elapsed().as_secs_f64() - FRAME_INTERVAL_SECS;
+        let delay_debt_s =
+            previous_frame_stamp.elapsed().as_secs_f64() - region.frame_interval_secs();
+        let controller_buttons = controller_input.read(&window, config);
+
         tx.send(FrameFinishedSignal {
-            current_keycode,
+            keycodes,
+            controller_buttons,
+            zapper_trigger: window.get_mouse_down(MouseButton::Left),
+            zapper_aim: read_zapper_aim(&window),
+            fast_forward: window.is_key_down(config.hotkeys.fast_forward.0),
             delay_debt_s,
         })?;
 
@@ -132,25 +195,91 @@ fn run_render_loop(
     Ok(())
 }
 
-fn process_input(window: &Window, color_toggles: &mut ColorToggles) -> Keycode {
-    let mut current_keycode = Keycode::Placeholder;
+fn process_input(
+    window: &Window,
+    hotkeys: &Hotkeys,
+    color_toggles: &mut ColorToggles,
+) -> Vec<Keycode> {
+    let mut keycodes = Vec::new();
 
-    if window.is_key_pressed(Key::O, KeyRepeat::No) {
+    if window.is_key_pressed(hotkeys.toggle_orange.0, KeyRepeat::No) {
         color_toggles.orange = !color_toggles.orange;
-        current_keycode = Keycode::ToggleOrange;
+        keycodes.push(Keycode::ToggleOrange);
     }
 
-    if window.is_key_pressed(Key::I, KeyRepeat::No) {
+    if window.is_key_pressed(hotkeys.toggle_indigo.0, KeyRepeat::No) {
         color_toggles.indigo = !color_toggles.indigo;
-        current_keycode = Keycode::ToggleIndigo;
+        keycodes.push(Keycode::ToggleIndigo);
+    }
+
+    if window.is_key_pressed(hotkeys.pause.0, KeyRepeat::No) {
+        keycodes.push(Keycode::TogglePause);
+    }
+
+    if window.is_key_pressed(hotkeys.reset.0, KeyRepeat::No) {
+        keycodes.push(Keycode::Reset);
+    }
+
+    keycodes
+}
+
+/// Reads the buttons held on the keyboard, and on the gamepads when the
+/// `gamepad` feature is enabled.
+struct ControllerInput {
+    #[cfg(feature = "gamepad")]
+    gamepads: Gamepads,
+}
+
+impl ControllerInput {
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "gamepad")]
+            gamepads: Gamepads::new(),
+        }
+    }
+
+    fn read(&mut self, window: &Window, config: &Config) -> [Buttons; 2] {
+        let mut controller_buttons = [Buttons::default(); 2];
+
+        for (port, bindings) in [&config.player1, &config.player2].into_iter().enumerate() {
+            for (key, button) in bindings.buttons() {
+                controller_buttons[port].set(button, window.is_key_down(key.0));
+            }
+        }
+
+        #[cfg(feature = "gamepad")]
+        self.gamepads.read_into(&mut controller_buttons);
+
+        controller_buttons
     }
+}
 
-    current_keycode
+fn read_zapper_aim(window: &Window) -> Option<(usize, usize)> {
+    let (x, y) = window.get_mouse_pos(MouseMode::Discard)?;
+    let (x, y) = (x as usize, y as usize);
+
+    // The picture is drawn in the top left corner of the window, next to the debug view.
+    (x < WIDTH && y < HEIGHT).then_some((x, y))
+}
+
+/// Saves the current frame to `screenshot-<unix time>.png` in the working directory.
+fn save_screenshot(pixels: &Pixels) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let path = PathBuf::from(format!("screenshot-{secs}.png"));
+
+    match pixels.save_png(&path) {
+        Ok(()) => println!("Saved screenshot to {}", path.display()),
+        Err(err) => eprintln!("Failed to save screenshot to {}: {err}", path.display()),
+    }
 }
 
 pub struct Emulator {
     cpu: Rc<RefCell<CpuContainer>>,
     ppu: Rc<RefCell<Ppu>>,
+    apu: Rc<RefCell<Apu>>,
+    controllers: Rc<RefCell<Controllers>>,
     cartridge: Rc<RefCell<Cartridge>>,
 }
 
@@ -158,32 +287,49 @@ impl Emulator {
     pub fn from_initialized(
         cpu: Rc<RefCell<CpuContainer>>,
         ppu: Rc<RefCell<Ppu>>,
+        apu: Rc<RefCell<Apu>>,
+        controllers: Rc<RefCell<Controllers>>,
         cartridge: Rc<RefCell<Cartridge>>,
     ) -> Self {
         Self {
             cpu,
             ppu,
+            apu,
+            controllers,
             cartridge,
         }
     }
 }
 
 struct EmulatorRunner {
+    region: Region,
+    pacing: Pacing,
+    paused: bool,
     cpu_cycle_debt: i64,
-    current_machine_cycles: u8,
+    /// Master clock cycles left until the PPU is clocked again.
+    machine_cycles_until_ppu_clock: u64,
     cpu_snapshot: CpuDebugSnapshot,
     startup_instruction_trace: StartupInstructionTrace,
+    resampler: Resampler,
+    audio_sink: Box<dyn AudioSink>,
+    audio_samples: Vec<f32>,
 }
 
 impl EmulatorRunner {
-    fn new() -> Self {
+    fn new(region: Region, pacing: Pacing, audio_sink: Box<dyn AudioSink>) -> Self {
         Self {
+            region,
+            pacing,
+            paused: false,
             cpu_cycle_debt: 0,
-            current_machine_cycles: 0,
+            machine_cycles_until_ppu_clock: 0,
             cpu_snapshot: CpuDebugSnapshot::default(),
             startup_instruction_trace: StartupInstructionTrace::new(
                 "startup_instruction_trace.txt",
             ),
+            resampler: Resampler::new(region.cpu_hz(), audio_sink.sample_rate()),
+            audio_sink,
+            audio_samples: Vec::new(),
         }
     }
 
@@ -196,10 +342,42 @@ impl EmulatorRunner {
         frame_finished_signal: FrameFinishedSignal,
     ) {
         self.save_completed_startup_trace();
-        handle_keycode(frame_finished_signal.current_keycode);
+        for keycode in frame_finished_signal.keycodes {
+            self.handle_keycode(emulator, keycode);
+        }
+
+        for (port, buttons) in frame_finished_signal
+            .controller_buttons
+            .into_iter()
+            .enumerate()
+        {
+            emulator.controllers.borrow_mut().set_buttons(port, buttons);
+        }
+        emulator.controllers.borrow_mut().set_zapper_input(
+            frame_finished_signal.zapper_trigger,
+            frame_finished_signal.zapper_aim,
+        );
 
-        let mut available_cpu_cycles = ((FRAME_INTERVAL_SECS + frame_finished_signal.delay_debt_s)
-            * CPU_HZ) as i64
+        if self.paused {
+            return;
+        }
+
+        let fast_forward = frame_finished_signal.fast_forward;
+        let frame_interval_secs = self.region.frame_interval_secs();
+        let delay_debt_s = match (self.pacing, self.audio_sink.buffered_secs()) {
+            // Run ahead while the audio buffer is running low, and fall behind while it fills up.
+            (Pacing::Audio, Some(buffered_secs)) if !fast_forward => (AUDIO_LATENCY_SECS
+                - buffered_secs)
+                .clamp(-frame_interval_secs, frame_interval_secs),
+            _ => frame_finished_signal.delay_debt_s,
+        };
+        let speed = match fast_forward {
+            true => FAST_FORWARD_SPEED,
+            false => 1.0,
+        };
+
+        let mut available_cpu_cycles = ((frame_interval_secs * speed + delay_debt_s)
+            * self.region.cpu_hz()) as i64
             + self.cpu_cycle_debt;
 
         loop {
@@ -214,7 +392,23 @@ impl EmulatorRunner {
             }
 
             available_cpu_cycles -= cpu_cycles_taken as i64;
-            self.clock_bus(emulator, pixels, cpu_cycles_taken);
+            self.clock_bus(emulator, pixels, cpu_cycles_taken as u16);
+
+            // DMC fetches can happen while the CPU is stalled, stalling it further.
+            loop {
+                let stall_cycles = emulator
+                    .cpu
+                    .borrow_mut()
+                    .take_stall_cycles(self.cpu_snapshot.total_cpu_cycles);
+
+                if stall_cycles == 0 {
+                    break;
+                }
+
+                self.cpu_snapshot.total_cpu_cycles += stall_cycles as u64;
+                available_cpu_cycles -= stall_cycles as i64;
+                self.clock_bus(emulator, pixels, stall_cycles);
+            }
 
             let ppu_snapshot = emulator.ppu.borrow().debug_snapshot();
             self.record_startup_trace(&ppu_snapshot);
@@ -226,18 +420,58 @@ impl EmulatorRunner {
                 break;
             }
         }
+
+        self.resampler.drain_into(&mut self.audio_samples);
+        // The audio would pile up in the sink while fast-forwarding, so it is dropped.
+        if !fast_forward {
+            self.audio_sink.write(&self.audio_samples);
+        }
+        self.audio_samples.clear();
     }
 
-    fn clock_bus(&mut self, emulator: &mut Emulator, pixels: &Pixels, cpu_cycles_taken: u8) {
-        let machine_cycles_taken = cpu_cycles_taken * CLOCK_DIVISOR as u8;
+    fn handle_keycode(&mut self, emulator: &mut Emulator, keycode: Keycode) {
+        match keycode {
+            Keycode::ToggleOrange | Keycode::ToggleIndigo => {}
+            Keycode::TogglePause => self.paused = !self.paused,
+            Keycode::Reset => {
+                emulator.cpu.borrow_mut().reset();
+                emulator.apu.borrow_mut().reset();
+                emulator.ppu.borrow_mut().reset();
+            }
+        }
+    }
 
-        for _ in 0..machine_cycles_taken {
-            if self.current_machine_cycles % PPU_CLOCK_DIVISOR == 0 {
-                emulator.ppu.borrow_mut().clock(pixels);
+    fn clock_bus(&mut self, emulator: &mut Emulator, pixels: &Pixels, cpu_cycles_taken: u16) {
+        for _ in 0..cpu_cycles_taken {
+            emulator.apu.borrow_mut().clock();
+            self.resampler.push(emulator.apu.borrow().output());
+
+            let dmc_sample_fetch_address = emulator.apu.borrow().dmc_sample_fetch_address();
+            if let Some(address) = dmc_sample_fetch_address {
+                let byte = emulator.cpu.borrow_mut().read_dmc_sample(address);
+                emulator.apu.borrow_mut().load_dmc_sample_byte(byte);
             }
 
-            emulator.cartridge.borrow_mut().clock();
-            self.current_machine_cycles = self.current_machine_cycles.wrapping_add(1);
+            // The IRQ line is shared, so either the APU or the cartridge can pull it.
+            let irq = emulator.apu.borrow().irq() || emulator.cartridge.borrow().irq();
+            emulator
+                .cpu
+                .borrow_mut()
+                .0
+                .interrupts
+                .set_interrupt_state(irq);
+
+            // The PPU divisor doesn't always divide the CPU divisor (PAL runs 3.2 dots
+            // per CPU cycle), so the PPU keeps its own phase across calls.
+            for _ in 0..self.region.cpu_clock_divisor() {
+                if self.machine_cycles_until_ppu_clock == 0 {
+                    emulator.ppu.borrow_mut().clock(pixels);
+                    self.machine_cycles_until_ppu_clock = self.region.ppu_clock_divisor();
+                }
+
+                emulator.cartridge.borrow_mut().clock();
+                self.machine_cycles_until_ppu_clock -= 1;
+            }
         }
     }
 
@@ -272,10 +506,3 @@ fn publish_debug_snapshots(
     *cpu_debug.lock().unwrap() = cpu_snapshot.clone();
     *ppu_debug.lock().unwrap() = ppu.borrow().debug_snapshot();
 }
-
-fn handle_keycode(keycode: Keycode) {
-    match keycode {
-        Keycode::Placeholder => {}
-        Keycode::ToggleOrange | Keycode::ToggleIndigo => {}
-    }
-}
@@ -0,0 +1,122 @@
+use crate::display::{Pixels, HEIGHT, WIDTH};
+use std::sync::Arc;
+
+/// Set while no light is sensed.
+const NO_LIGHT: u8 = 0b0000_1000;
+/// Set while the trigger is pulled.
+const TRIGGER: u8 = 0b0001_0000;
+/// The photodiode keeps reporting light for a while after the beam has passed
+/// the spot it is aimed at, which is about this many scanlines.
+const LIGHT_SCANLINES: usize = 20;
+/// How many pixels around the aim the photodiode sees in each direction.
+const SENSE_RADIUS: usize = 2;
+/// The perceived brightness a pixel needs to count as light, from 0 to 255.
+/// This is high enough that the blue sky of Duck Hunt doesn't count.
+const LIGHT_THRESHOLD: f32 = 160.0;
+
+/// The Zapper light gun. It doesn't have a shift register, the trigger and the
+/// light sensor are read directly on bits 4 and 3.
+pub struct Zapper {
+    pixels: Arc<Pixels>,
+    trigger: bool,
+    /// The pixel the Zapper is aimed at, or `None` when it's off screen.
+    aim: Option<(usize, usize)>,
+}
+
+impl Zapper {
+    pub fn new(pixels: Arc<Pixels>) -> Self {
+        Self {
+            pixels,
+            trigger: false,
+            aim: None,
+        }
+    }
+
+    pub fn set_input(&mut self, trigger: bool, aim: Option<(usize, usize)>) {
+        self.trigger = trigger;
+        self.aim = aim;
+    }
+
+    /// Reads the Zapper while the PPU is at `scanline` and `dot`.
+    pub fn read(&self, scanline: usize, dot: usize) -> u8 {
+        let mut byte = 0;
+
+        if !self.senses_light(scanline, dot) {
+            byte |= NO_LIGHT;
+        }
+        if self.trigger {
+            byte |= TRIGGER;
+        }
+
+        byte
+    }
+
+    /// Light is only sensed from bright pixels around the aim that the beam drew
+    /// in the last [`LIGHT_SCANLINES`] scanlines.
+    fn senses_light(&self, scanline: usize, dot: usize) -> bool {
+        let Some((x, y)) = self.aim else {
+            return false;
+        };
+
+        let rows = y.saturating_sub(SENSE_RADIUS)..=(y + SENSE_RADIUS).min(HEIGHT - 1);
+        let columns = x.saturating_sub(SENSE_RADIUS)..=(x + SENSE_RADIUS).min(WIDTH - 1);
+
+        rows.flat_map(|row| columns.clone().map(move |column| (column, row)))
+            // Dot 1 draws the pixel at x = 0.
+            .filter(|&(column, row)| row < scanline || (row == scanline && column + 1 < dot))
+            .filter(|&(_, row)| scanline < row + LIGHT_SCANLINES)
+            .any(|(column, row)| {
+                let rgb = self.pixels.read(column, row);
+                let brightness = 0.299 * rgb.r as f32 + 0.587 * rgb.g as f32 + 0.114 * rgb.b as f32;
+                brightness >= LIGHT_THRESHOLD
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rgb::Rgb;
+
+    const WHITE: Rgb<u8> = Rgb {
+        r: 0xFF,
+        g: 0xFF,
+        b: 0xFF,
+    };
+
+    fn zapper_aimed_at_white_box() -> Zapper {
+        let pixels = Arc::new(Pixels::new());
+        for y in 100..110 {
+            for x in 50..60 {
+                pixels.write(x, y, WHITE);
+            }
+        }
+
+        let mut zapper = Zapper::new(pixels);
+        zapper.set_input(false, Some((55, 105)));
+        zapper
+    }
+
+    #[test]
+    fn light_is_sensed_for_a_while_after_the_beam_passes() {
+        let zapper = zapper_aimed_at_white_box();
+
+        // The aim is at (55, 105), and the Zapper sees the rows 103 to 107.
+        assert_eq!(zapper.read(90, 0), NO_LIGHT);
+        assert_eq!(zapper.read(103, 50), NO_LIGHT);
+        assert_eq!(zapper.read(103, 100), 0);
+        assert_eq!(zapper.read(107 + LIGHT_SCANLINES - 1, 0), 0);
+        assert_eq!(zapper.read(107 + LIGHT_SCANLINES, 0), NO_LIGHT);
+    }
+
+    #[test]
+    fn dark_pixels_and_off_screen_aims_sense_no_light() {
+        let mut zapper = zapper_aimed_at_white_box();
+
+        zapper.set_input(true, Some((20, 105)));
+        assert_eq!(zapper.read(110, 0), NO_LIGHT | TRIGGER);
+
+        zapper.set_input(true, None);
+        assert_eq!(zapper.read(110, 0), NO_LIGHT | TRIGGER);
+    }
+}